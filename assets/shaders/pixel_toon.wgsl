@@ -0,0 +1,121 @@
+#import bevy_pbr::{
+    pbr_fragment::pbr_input_from_standard_material,
+    pbr_functions::{alpha_discard, apply_pbr_lighting, main_pass_post_lighting_processing},
+    forward_io::{VertexOutput, FragmentOutput},
+    mesh_view_bindings::{lights, view},
+    mesh_types::MESH_FLAGS_SHADOW_RECEIVER_BIT,
+    mesh_view_types::DIRECTIONAL_LIGHT_FLAGS_SHADOWS_ENABLED_BIT,
+    pbr_types::PbrInput,
+    shadows::fetch_directional_shadow,
+}
+
+struct PixelToonExtension {
+    bands: u32,
+    specular_strength: f32,
+    specular_size: f32,
+    rim_strength: f32,
+    rim_size: f32,
+    rim_color: vec4<f32>,
+}
+
+@group(2) @binding(100) var<uniform> toon: PixelToonExtension;
+@group(2) @binding(101) var ramp_texture: texture_2d<f32>;
+@group(2) @binding(102) var ramp_sampler: sampler;
+
+@fragment
+fn fragment(in: VertexOutput, @builtin(front_facing) is_front: bool) -> FragmentOutput {
+    var pbr_input = pbr_input_from_standard_material(in, is_front);
+    pbr_input.material.base_color = alpha_discard(pbr_input.material, pbr_input.material.base_color);
+    let base_color = pbr_input.material.base_color;
+
+    // Light a white, fully rough surface to get only the diffuse lighting,
+    // then split it into its brightness (which gets banded) and its colour.
+    var diffuse_input = pbr_input;
+    diffuse_input.material.base_color = vec4(1.0, 1.0, 1.0, base_color.a);
+    diffuse_input.material.emissive = vec4(0.0);
+    diffuse_input.material.metallic = 0.0;
+    diffuse_input.material.perceptual_roughness = 1.0;
+    diffuse_input.material.reflectance = vec3(0.0);
+    var diffuse = apply_pbr_lighting(diffuse_input).rgb;
+
+#ifdef TOON_DITHER_SHADOWS
+    // Swap the main light's filtered shadow for an ordered dither of it:
+    // light the surface again unshadowed, take out the main light's share
+    // and add it back only where the dither pattern says it's lit.
+    let visibility = main_light_visibility(diffuse_input);
+    if visibility > 0.0 && visibility < 1.0 {
+        var unshadowed_input = diffuse_input;
+        unshadowed_input.flags &= ~MESH_FLAGS_SHADOW_RECEIVER_BIT;
+        let unshadowed = apply_pbr_lighting(unshadowed_input).rgb;
+        let direct = (unshadowed - diffuse) / (1.0 - visibility);
+        let dithered = step(bayer_threshold(vec2<i32>(in.position.xy)), visibility);
+        diffuse = unshadowed - (1.0 - dithered) * direct;
+    }
+#endif
+
+    let intensity = max(diffuse.r, max(diffuse.g, diffuse.b));
+    let tint = diffuse / max(intensity, 0.0001);
+
+    var color = base_color.rgb * tint * toon_band(intensity);
+
+    // Hard-edged specular from the main directional light
+    if lights.n_directional_lights > 0u {
+        let light = lights.directional_lights[0];
+        let half_vector = normalize(light.direction_to_light + pbr_input.V);
+        let n_dot_h = max(dot(pbr_input.N, half_vector), 0.0);
+        let lit = step(0.0, dot(pbr_input.N, light.direction_to_light));
+        let highlight = step(1.0 - toon.specular_size * toon.specular_size, n_dot_h) * lit;
+        color += highlight * toon.specular_strength * light.color.rgb * view.exposure;
+    }
+
+    // Rim light along the silhouette
+    let rim = step(1.0 - toon.rim_size, 1.0 - max(dot(pbr_input.N, pbr_input.V), 0.0));
+    color += rim * toon.rim_strength * toon.rim_color.rgb;
+
+    let emissive = pbr_input.material.emissive;
+    color += emissive.rgb * mix(1.0, view.exposure, emissive.a);
+
+    var out: FragmentOutput;
+    out.color = main_pass_post_lighting_processing(pbr_input, vec4(color, base_color.a));
+    return out;
+}
+
+// Snaps the lighting intensity to one of `toon.bands` levels.
+fn toon_band(intensity: f32) -> vec3<f32> {
+    let bands = f32(max(toon.bands, 1u));
+    let band = min(floor(intensity * bands), bands - 1.0);
+#ifdef TOON_RAMP
+    return textureSampleLevel(ramp_texture, ramp_sampler, vec2((band + 0.5) / bands, 0.5), 0.0).rgb;
+#else
+    return vec3((band + 1.0) / bands);
+#endif
+}
+
+#ifdef TOON_DITHER_SHADOWS
+// How much of the first directional light reaches the surface, from 0 to 1.
+fn main_light_visibility(pbr_input: PbrInput) -> f32 {
+    if lights.n_directional_lights == 0u
+        || (pbr_input.flags & MESH_FLAGS_SHADOW_RECEIVER_BIT) == 0u
+        || (lights.directional_lights[0].flags & DIRECTIONAL_LIGHT_FLAGS_SHADOWS_ENABLED_BIT) == 0u {
+        return 1.0;
+    }
+    let view_z = dot(vec4(
+        view.view_from_world[0].z,
+        view.view_from_world[1].z,
+        view.view_from_world[2].z,
+        view.view_from_world[3].z
+    ), pbr_input.world_position);
+    return fetch_directional_shadow(0u, pbr_input.world_position, pbr_input.world_normal, view_z);
+}
+
+// 4x4 Bayer threshold in (0, 1) for a texel.
+fn bayer_threshold(uv: vec2<i32>) -> f32 {
+    var bayer = array<f32, 16>(
+        0.0, 8.0, 2.0, 10.0,
+        12.0, 4.0, 14.0, 6.0,
+        3.0, 11.0, 1.0, 9.0,
+        15.0, 7.0, 13.0, 5.0,
+    );
+    return (bayer[(uv.y % 4) * 4 + (uv.x % 4)] + 0.5) / 16.0;
+}
+#endif
@@ -0,0 +1,25 @@
+#import bevy_sprite::{
+    mesh2d_vertex_output::VertexOutput,
+    mesh2d_view_bindings::view,
+}
+
+#ifdef TONEMAP_IN_SHADER
+#import bevy_core_pipeline::tonemapping
+#endif
+
+@group(2) @binding(0) var texture: texture_2d<f32>;
+@group(2) @binding(1) var texture_sampler: sampler;
+
+// the pixel UI camera blends into a transparent texture, which leaves its colour premultiplied by alpha
+@fragment
+fn fragment(mesh: VertexOutput) -> @location(0) vec4<f32> {
+    var color = textureSample(texture, texture_sampler, mesh.uv);
+
+#ifdef TONEMAP_IN_SHADER
+    if color.a > 0.0 {
+        let straight = tonemapping::tone_mapping(vec4(color.rgb / color.a, color.a), view.color_grading);
+        color = vec4(straight.rgb * color.a, color.a);
+    }
+#endif
+    return color;
+}
@@ -4,8 +4,7 @@
     globals::Globals,
 }
 struct PostProcessSettings {
-    show_depth: u32,
-    show_normals: u32,
+    color_depth: u32,
 }
 
 @group(0) @binding(0) var screen_texture: texture_2d<f32>;
@@ -22,7 +21,62 @@ const errorCarry = 0.3;
 @fragment
 fn fragment(in: FullscreenVertexOutput) -> @location(0) vec4<f32> {
     let uv = vec2<i32>(in.position.xy);
+    let outline_mask = floor(textureLoad(prepass_normal_texture, uv, 0).a);
+
+#ifdef DEBUG_LINEAR_DEPTH
+    let depth = -get_linear_depth(uv, view.clip_from_view, outline_mask) * 0.5;
+    return vec4(depth, depth, depth, 1.);
+#else ifdef DEBUG_DEPTH_EDGES
+    let depth_diff = get_edges(uv, outline_mask).x;
+    return vec4(depth_diff, depth_diff, depth_diff, 1.);
+#else ifdef DEBUG_NORMAL_EDGES
+    let normal_diff = get_edges(uv, outline_mask).y;
+    return vec4(normal_diff, normal_diff, normal_diff, 1.);
+#else ifdef DEBUG_RAW_NORMALS
+    return vec4(textureLoad(prepass_normal_texture, uv, 0).rgb, 1.);
+#else ifdef DEBUG_OUTLINE_MASK
+    return vec4(outline_mask, outline_mask, outline_mask, 1.);
+#else ifdef DEBUG_PALETTE_INDEX
+    let texel = textureSample(screen_texture, texture_sampler, in.uv);
+    let scale = exp2(f32(settings.color_depth)) - 1.0;
+    let lum = dot(texel.rgb, vec3(0.2126, 0.7152, 0.0722));
+    let index = floor(lum * scale + 0.5) / scale;
+    return vec4(index, index, index, 1.);
+#else ifdef DEBUG_OBJECT_IDS
+    return vec4(surface_color(uv, in.uv), 1.);
+#else
+    var texel = textureSample(screen_texture, texture_sampler, in.uv);
+
+#ifdef PALETTE
+#ifdef DITHER
+    texel = dither(texel, uv, i32(settings.color_depth));
+#endif
+    texel = quantize(texel, i32(settings.color_depth));
+#endif
+
+#ifdef OUTLINE
+    let edges = get_edges(uv, outline_mask);
+    let depth_diff = edges.x;
+    let normal_diff = edges.y;
+
+    let line_highlight = 1.2;
+    let line_shadow = 0.55;
+    texel += texel * clamp((normal_diff - depth_diff), 0.0, 1.0) * line_highlight;
+    texel -= texel * depth_diff * line_shadow;
+#endif
+
+#ifdef DEBUG_TEXEL_GRID
+    if (uv.x + uv.y) % 2 == 0 {
+        texel = vec4(texel.rgb * 0.75, texel.a);
+    }
+#endif
+
+    return texel;
+#endif
+}
 
+// Returns the depth edge strength in x and the normal edge strength in y.
+fn get_edges(uv: vec2<i32>, outline_mask: f32) -> vec2<f32> {
     let uv_offsets = array<vec2<i32>, 4>(
 	    vec2(uv + vec2(0, -1)),
 	    vec2(uv + vec2(0, 1) ),
@@ -30,13 +84,8 @@ fn fragment(in: FullscreenVertexOutput) -> @location(0) vec4<f32> {
 	    vec2(uv + vec2(-1, 0)) 
     );
 
-    var outline_mask = textureLoad(prepass_normal_texture, uv, 0).a;
-	outline_mask = floor(outline_mask);
-	
 	// Roberts Cross edge detection
 	// Edge detection with Depth
-    // var depth = textureLoad(depth_texture, uv, 0);
-	let depth = -get_linear_depth(uv, view.clip_from_view, outline_mask) * 0.5;
 	let d = get_depth_difference(uv, outline_mask);
     let depth_diff = d.x;
     let neg_depth_diff = d.y;
@@ -53,25 +102,28 @@ fn fragment(in: FullscreenVertexOutput) -> @location(0) vec4<f32> {
 	normal_diff = smoothstep(0.5, 0.9, normal_diff);
 	normal_diff = clamp(normal_diff - neg_depth_diff, 0.0, 1.0);
 
+    return vec2(depth_diff, normal_diff);
+}
 
-    var texel = textureSample(screen_texture, texture_sampler, in.uv);
-	// ALBEDO = texture(SCREEN_TEXTURE, SCREEN_UV).rgb; 
-	let line_mask = clamp(0.1, 0.7, (depth_diff + normal_diff * 5.0));
-    
-    if settings.show_depth == 1u {
-        return vec4(depth, depth, depth, 1.);
-    } else if settings.show_normals == 1u {
-        return vec4(depth_diff, depth_diff, depth_diff, 1.);
+// Colours a texel by the plane its surface lies on: the world normal
+// and the plane's distance from the origin, hashed into a colour.
+fn surface_color(uv: vec2<i32>, screen_uv: vec2<f32>) -> vec3<f32> {
+    let normal = textureLoad(prepass_normal_texture, uv, 0).xyz * 2.0 - 1.0;
+    let depth = textureLoad(depth_texture, uv, 0);
+    if depth == 0.0 {
+        return vec3(0.0);
     }
-
-    let line_highlight = 1.2;
-    let line_shadow = 0.55;
-    // let lum = 0.2126 * texel.r + 0.7152 * texel.g + 0.0722 * texel.b;
-    // texel = vec4(lum, lum, lum, 1.);
-    texel = quantize(texel, 8);
-    texel += texel * clamp((normal_diff - depth_diff), 0.0, 1.0) * line_highlight;
-    texel -= texel * depth_diff * line_shadow;
-    return texel;
+    let ndc = vec4(screen_uv.x * 2.0 - 1.0, 1.0 - screen_uv.y * 2.0, depth, 1.0);
+    let world = view.world_from_clip * ndc;
+    let distance = dot(normal, world.xyz / world.w);
+
+    let n = vec3<u32>(round((normal * 0.5 + 0.5) * 15.0));
+    var id = n.x | (n.y << 4u) | (n.z << 8u) | (bitcast<u32>(i32(round(distance))) << 12u);
+    // PCG hash
+    id = id * 747796405u + 2891336453u;
+    id = ((id >> ((id >> 28u) + 4u)) ^ id) * 277803737u;
+    id = (id >> 22u) ^ id;
+    return vec3(f32(id & 255u), f32((id >> 8u) & 255u), f32((id >> 16u) & 255u)) / 255.0;
 }
 
 fn quantize(texel: vec4<f32>, amount: i32) -> vec4<f32> {
@@ -79,6 +131,21 @@ fn quantize(texel: vec4<f32>, amount: i32) -> vec4<f32> {
 	return floor(texel * scale + 0.5f) / scale;
 }
 
+// Offsets the colour by a 4x4 Bayer threshold of one quantization step,
+// so gradients break up into a pattern instead of banding.
+fn dither(texel: vec4<f32>, uv: vec2<i32>, amount: i32) -> vec4<f32> {
+    var bayer = array<f32, 16>(
+        0.0, 8.0, 2.0, 10.0,
+        12.0, 4.0, 14.0, 6.0,
+        3.0, 11.0, 1.0, 9.0,
+        15.0, 7.0, 13.0, 5.0,
+    );
+    let index = (uv.y % 4) * 4 + (uv.x % 4);
+    let threshold = (bayer[index] + 0.5) / 16.0 - 0.5;
+    let scale = exp2(f32(amount)) - 1.0;
+    return vec4(texel.rgb + threshold / scale, texel.a);
+}
+
 fn get_depth_difference(uv: vec2<i32>, outline_mask: f32) -> vec2<f32>{
     let uv_offsets = array<vec2<i32>, 4>(
 	    vec2(uv + vec2(0, -1)),
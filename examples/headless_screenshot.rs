@@ -0,0 +1,101 @@
+//! Renders the pixel canvas without a window and saves it to a PNG, for CI thumbnails.
+//!
+//! Writes the canvas at its native 640x360 to `screenshots/canvas.png` (pass a path as the
+//! first argument to change it), plus a 4x nearest-neighbour copy next to it, then exits.
+//!
+//! Run it with `cargo run --example headless_screenshot -- out/thumbnail.png`.
+
+use std::time::Duration;
+
+use bevy::{
+    app::ScheduleRunnerPlugin,
+    prelude::*,
+    window::ExitCondition,
+    winit::WinitPlugin,
+};
+use bevy_pixelated_3d::*;
+
+/// Frames to render before capturing, so pipelines have time to compile.
+const WARMUP_FRAMES: u32 = 120;
+
+#[derive(Resource)]
+struct OutputPath(String);
+
+fn main() {
+    let path = std::env::args()
+        .nth(1)
+        .unwrap_or_else(|| "screenshots/canvas.png".into());
+
+    App::new()
+        .insert_resource(OutputPath(path))
+        .insert_resource(PixelCamSettings {
+            // bloom needs float render targets that software adapters may not have
+            hdr: false,
+            outer_camera: false,
+            ..default()
+        })
+        .add_plugins((
+            DefaultPlugins
+                .set(ImagePlugin::default_nearest())
+                .set(WindowPlugin {
+                    primary_window: None,
+                    exit_condition: ExitCondition::DontExit,
+                    ..default()
+                })
+                .disable::<WinitPlugin>(),
+            ScheduleRunnerPlugin::run_loop(Duration::from_secs_f64(1. / 60.)),
+            PixelCamPlugin,
+            PostProcessPlugin,
+            PixelScreenshotPlugin,
+        ))
+        .add_systems(Startup, setup)
+        .add_systems(Update, (capture, exit_when_saved))
+        .run();
+}
+
+fn setup(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    commands.spawn((
+        Mesh3d(meshes.add(Cuboid::from_size(Vec3::new(90., 0., 90.)))),
+        MeshMaterial3d(materials.add(Color::linear_rgb(0.5, 0.5, 0.5))),
+    ));
+    commands.spawn((
+        Mesh3d(meshes.add(Capsule3d::new(6., 12.))),
+        MeshMaterial3d(materials.add(Color::linear_rgb(0.1, 0.5, 0.1))),
+        Transform::from_xyz(0., 12., 0.),
+    ));
+    commands.spawn((
+        DirectionalLight {
+            shadows_enabled: true,
+            illuminance: 4000.,
+            ..default()
+        },
+        Transform::from_xyz(1., 2., 1.).looking_at(Vec3::ZERO, Vec3::Y),
+    ));
+}
+
+fn capture(
+    path: Res<OutputPath>,
+    mut frames: Local<u32>,
+    mut screenshots: EventWriter<PixelScreenshot>,
+) {
+    *frames += 1;
+    if *frames == WARMUP_FRAMES {
+        screenshots.write(PixelScreenshot::new(&path.0).with_upscale(4));
+    }
+}
+
+fn exit_when_saved(
+    mut saved: EventReader<PixelScreenshotSaved>,
+    mut exit: EventWriter<AppExit>,
+) {
+    for saved in saved.read() {
+        exit.write(match saved.result {
+            Ok(()) => AppExit::Success,
+            Err(_) => AppExit::error(),
+        });
+    }
+}
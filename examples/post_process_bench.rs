@@ -0,0 +1,153 @@
+//! Benchmark scene for the post-process pass with many views.
+//!
+//! Spawns `CAMERAS` pixel cameras (16 by default, pass a number as the first argument to change it),
+//! each rendering the same scene to its own texture with `PostProcessSettings`, then prints the
+//! average frame time after a fixed number of frames and exits.
+//!
+//! Run it with `cargo run --release --example post_process_bench -- 32` and compare the result
+//! against a build of an earlier revision to measure changes to the post-process node.
+//! Add `--uncached` to throw away every view's bind groups each frame, which shows what the
+//! bind group cache saves in the same build.
+
+use bevy::{
+    diagnostic::{DiagnosticsStore, FrameTimeDiagnosticsPlugin, DEFAULT_MAX_HISTORY_LENGTH},
+    prelude::*,
+    render::{
+        render_resource::{Extent3d, TextureDescriptor, TextureDimension, TextureFormat, TextureUsages},
+        Render, RenderApp, RenderSet,
+    },
+};
+use bevy_pixelated_3d::*;
+
+/// Number of frames to render before reporting.
+const FRAMES: u32 = 600;
+
+#[derive(Resource)]
+struct BenchCameras {
+    count: u32,
+    uncached: bool,
+}
+
+fn main() {
+    let cameras = std::env::args()
+        .skip(1)
+        .find_map(|arg| arg.parse().ok())
+        .unwrap_or(16);
+    let uncached = std::env::args().any(|arg| arg == "--uncached");
+
+    let mut app = App::new();
+    app
+        .insert_resource(BenchCameras { count: cameras, uncached })
+        .add_plugins((
+            DefaultPlugins,
+            FrameTimeDiagnosticsPlugin::default(),
+            PostProcessPlugin,
+        ))
+        .add_systems(Startup, setup)
+        .add_systems(Update, report);
+    if uncached {
+        // before the bind groups are prepared, so they are rebuilt for every view
+        app.sub_app_mut(RenderApp)
+            .add_systems(Render, drop_bind_groups.in_set(RenderSet::PrepareResources));
+    }
+    app.run();
+}
+
+fn drop_bind_groups(
+    mut commands: Commands,
+    views: Query<Entity, With<PostProcessBindGroups>>,
+) {
+    for view in &views {
+        commands.entity(view).remove::<PostProcessBindGroups>();
+    }
+}
+
+fn setup(
+    mut commands: Commands,
+    cameras: Res<BenchCameras>,
+    mut images: ResMut<Assets<Image>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    let cube = meshes.add(Cuboid::from_length(1.));
+    let material = materials.add(Color::linear_rgb(0.5, 0.5, 0.5));
+    for x in -5..=5 {
+        for z in -5..=5 {
+            commands.spawn((
+                Mesh3d(cube.clone()),
+                MeshMaterial3d(material.clone()),
+                Transform::from_xyz(x as f32 * 2., 0., z as f32 * 2.),
+            ));
+        }
+    }
+    commands.spawn((
+        DirectionalLight::default(),
+        Transform::from_xyz(1., 2., 1.).looking_at(Vec3::ZERO, Vec3::Y),
+    ));
+
+    for i in 0..cameras.count {
+        let size = Extent3d {
+            width: RES_WIDTH,
+            height: RES_HEIGHT,
+            ..default()
+        };
+        let mut target = Image {
+            texture_descriptor: TextureDescriptor {
+                label: None,
+                size,
+                dimension: TextureDimension::D2,
+                format: TextureFormat::Bgra8UnormSrgb,
+                mip_level_count: 1,
+                sample_count: 1,
+                usage: TextureUsages::TEXTURE_BINDING
+                    | TextureUsages::COPY_DST
+                    | TextureUsages::RENDER_ATTACHMENT,
+                view_formats: &[],
+            },
+            ..default()
+        };
+        target.resize(size);
+        let angle = i as f32 / cameras.count as f32 * std::f32::consts::TAU;
+        commands.spawn((
+            Camera3d::default(),
+            Camera {
+                hdr: true,
+                order: i as isize - cameras.count as isize,
+                target: images.add(target).into(),
+                ..default()
+            },
+            Transform::from_xyz(angle.cos() * 20., 10., angle.sin() * 20.)
+                .looking_at(Vec3::ZERO, Vec3::Y),
+            PostProcessSettings::default(),
+        ));
+    }
+
+    // Something has to present to the window, otherwise the frame rate is not representative.
+    commands.spawn(Camera2d);
+}
+
+fn report(
+    diagnostics: Res<DiagnosticsStore>,
+    cameras: Res<BenchCameras>,
+    mut frames: Local<u32>,
+    mut exit: EventWriter<AppExit>,
+) {
+    *frames += 1;
+    if *frames < FRAMES {
+        return;
+    }
+
+    if let Some(frame_time) = diagnostics
+        .get(&FrameTimeDiagnosticsPlugin::FRAME_TIME)
+        .and_then(|frame_time| frame_time.average())
+    {
+        info!(
+            "{} cameras, bind groups {}: {:.3} ms average frame time over the last {} frames",
+            cameras.count,
+            if cameras.uncached { "rebuilt every frame" } else { "cached" },
+            frame_time,
+            DEFAULT_MAX_HISTORY_LENGTH,
+        );
+    }
+    exit.write(AppExit::Success);
+}
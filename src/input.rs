@@ -0,0 +1,194 @@
+use bevy::{input::InputSystem, prelude::*};
+
+/// Something the player can do, bound to keys and gamepad buttons in [`PixelInputMap`].
+/// Read the current state from `ButtonInput<PixelAction>`, or the combined axes from [`PixelInput`].
+#[derive(Reflect, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum PixelAction {
+    MoveLeft,
+    MoveRight,
+    MoveUp,
+    MoveDown,
+    Jump,
+    ZoomIn,
+    ZoomOut,
+    RotateLeft,
+    RotateRight,
+    CycleDebugView,
+}
+
+impl PixelAction {
+    pub const ALL: [PixelAction; 10] = [
+        PixelAction::MoveLeft,
+        PixelAction::MoveRight,
+        PixelAction::MoveUp,
+        PixelAction::MoveDown,
+        PixelAction::Jump,
+        PixelAction::ZoomIn,
+        PixelAction::ZoomOut,
+        PixelAction::RotateLeft,
+        PixelAction::RotateRight,
+        PixelAction::CycleDebugView,
+    ];
+}
+
+/// Bindings from keys and gamepad inputs to [`PixelAction`]s, and how fast the actions move things.
+/// Insert it before adding [`PixelInputPlugin`] to change the defaults.
+#[derive(Resource, Reflect, Clone, Debug)]
+#[reflect(Resource, Default)]
+pub struct PixelInputMap {
+    pub keys: Vec<(KeyCode, PixelAction)>,
+    pub buttons: Vec<(GamepadButton, PixelAction)>,
+    /// Gamepad stick axes that move, on top of the move actions.
+    pub move_axes: (GamepadAxis, GamepadAxis),
+    /// Gamepad axis that rotates the camera, on top of the rotate actions.
+    pub rotate_axis: GamepadAxis,
+    /// Stick values below this are ignored.
+    pub dead_zone: f32,
+    /// World units per second the player moves.
+    pub move_speed: f32,
+    /// Zoom levels per second.
+    pub zoom_speed: f32,
+    /// Radians per second the camera rotates.
+    pub rotate_speed: f32,
+}
+
+impl Default for PixelInputMap {
+    fn default() -> Self {
+        Self {
+            keys: vec![
+                (KeyCode::KeyA, PixelAction::MoveLeft),
+                (KeyCode::KeyD, PixelAction::MoveRight),
+                (KeyCode::KeyW, PixelAction::MoveUp),
+                (KeyCode::KeyS, PixelAction::MoveDown),
+                (KeyCode::ArrowLeft, PixelAction::MoveLeft),
+                (KeyCode::ArrowRight, PixelAction::MoveRight),
+                (KeyCode::ArrowUp, PixelAction::MoveUp),
+                (KeyCode::ArrowDown, PixelAction::MoveDown),
+                (KeyCode::Space, PixelAction::Jump),
+                (KeyCode::KeyE, PixelAction::ZoomIn),
+                (KeyCode::KeyQ, PixelAction::ZoomOut),
+                (KeyCode::KeyZ, PixelAction::RotateLeft),
+                (KeyCode::KeyX, PixelAction::RotateRight),
+                (KeyCode::Tab, PixelAction::CycleDebugView),
+            ],
+            buttons: vec![
+                (GamepadButton::DPadLeft, PixelAction::MoveLeft),
+                (GamepadButton::DPadRight, PixelAction::MoveRight),
+                (GamepadButton::DPadUp, PixelAction::MoveUp),
+                (GamepadButton::DPadDown, PixelAction::MoveDown),
+                (GamepadButton::South, PixelAction::Jump),
+                (GamepadButton::RightTrigger, PixelAction::ZoomIn),
+                (GamepadButton::LeftTrigger, PixelAction::ZoomOut),
+                (GamepadButton::Select, PixelAction::CycleDebugView),
+            ],
+            move_axes: (GamepadAxis::LeftStickX, GamepadAxis::LeftStickY),
+            rotate_axis: GamepadAxis::RightStickX,
+            dead_zone: 0.15,
+            move_speed: 50.,
+            zoom_speed: 10.,
+            rotate_speed: 1.5,
+        }
+    }
+}
+
+/// Actions combined into axes, from -1 to 1, updated every frame from [`PixelInputMap`].
+#[derive(Resource, Reflect, Default, Clone, Copy, Debug)]
+#[reflect(Resource, Default)]
+pub struct PixelInput {
+    /// Right and up, on the screen.
+    pub movement: Vec2,
+    /// Positive zooms in.
+    pub zoom: f32,
+    /// Positive rotates the camera clockwise, seen from above.
+    pub rotate: f32,
+}
+
+/// Maps keys and gamepad buttons to [`PixelAction`]s and the [`PixelInput`] axes.
+/// [`PixelCamPlugin`](crate::PixelCamPlugin) and [`PlayerPlugin`](crate::PlayerPlugin) set up
+/// the same input, so adding it too, before or after them, changes nothing.
+pub struct PixelInputPlugin;
+
+/// Marks that the input resources and systems are set up.
+#[derive(Resource)]
+struct PixelInputReady;
+
+impl Plugin for PixelInputPlugin {
+    fn build(&self, app: &mut App) {
+        init_pixel_input(app);
+    }
+}
+
+/// Sets up what [`PixelInputPlugin`] adds, once, for every plugin that reads input.
+pub(crate) fn init_pixel_input(app: &mut App) {
+    if app.world().contains_resource::<PixelInputReady>() {
+        return;
+    }
+    app
+    .insert_resource(PixelInputReady)
+    .register_type::<PixelAction>()
+    .register_type::<PixelInputMap>()
+    .register_type::<PixelInput>()
+    .init_resource::<PixelInputMap>()
+    .init_resource::<PixelInput>()
+    .init_resource::<ButtonInput<PixelAction>>()
+    .add_systems(PreUpdate, update_actions.after(InputSystem));
+}
+
+fn update_actions(
+    map: Res<PixelInputMap>,
+    keys: Res<ButtonInput<KeyCode>>,
+    gamepads: Query<&Gamepad>,
+    mut actions: ResMut<ButtonInput<PixelAction>>,
+    mut input: ResMut<PixelInput>,
+){
+    actions.clear();
+    for action in PixelAction::ALL {
+        let pressed = map.keys.iter().any(|&(key, bound)| bound == action && keys.pressed(key))
+            || map.buttons.iter().any(|&(button, bound)| {
+                bound == action && gamepads.iter().any(|gamepad| gamepad.pressed(button))
+            });
+        if pressed && !actions.pressed(action) {
+            actions.press(action);
+        } else if !pressed && actions.pressed(action) {
+            actions.release(action);
+        }
+    }
+
+    let axis = |negative, positive| {
+        actions.pressed(positive) as i32 as f32 - actions.pressed(negative) as i32 as f32
+    };
+    let stick = |axis| {
+        gamepads
+            .iter()
+            .filter_map(|gamepad| gamepad.get(axis))
+            .find(|value| value.abs() > map.dead_zone)
+            .unwrap_or(0.)
+    };
+
+    let movement = Vec2::new(
+        axis(PixelAction::MoveLeft, PixelAction::MoveRight) + stick(map.move_axes.0),
+        axis(PixelAction::MoveDown, PixelAction::MoveUp) + stick(map.move_axes.1),
+    );
+    *input = PixelInput {
+        movement: movement.clamp_length_max(1.),
+        zoom: axis(PixelAction::ZoomOut, PixelAction::ZoomIn),
+        rotate: (axis(PixelAction::RotateLeft, PixelAction::RotateRight) + stick(map.rotate_axis)).clamp(-1., 1.),
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::PlayerPlugin;
+
+    #[test]
+    fn input_plugin_can_come_after_the_plugins_using_it() {
+        let mut app = App::new();
+        app.add_plugins((PlayerPlugin, PixelInputPlugin));
+        assert!(app.world().contains_resource::<ButtonInput<PixelAction>>());
+
+        let mut app = App::new();
+        app.add_plugins((PixelInputPlugin, PlayerPlugin));
+        assert!(app.world().contains_resource::<PixelInput>());
+    }
+}
@@ -1,5 +1,7 @@
+mod input;
 mod player;
 mod pixelate;
 
+pub use input::*;
 pub use pixelate::*;
 pub use player::*;
@@ -1,29 +1,40 @@
 //! Shows how to create graphics that snap to the pixel grid by rendering to a texture in 2D
 
-use bevy::{asset::AssetMetaCheck, prelude::*};
+use bevy::{asset::{AssetMetaCheck, RenderAssetUsages}, prelude::*, render::render_resource::{Extent3d, TextureDimension, TextureFormat}};
 use bevy_pixelated_3d::*;
 use std::f32::consts::PI;
 
 
-#[derive(Resource)]
-pub struct ShowSettings{
-    value: i32
-}
-
 fn main() {
     App::new()
-    .insert_resource(ShowSettings{value: 0})
+    .insert_resource(PixelCamSettings {
+        pixel_ui: true,
+        ..default()
+    })
     .add_plugins(DefaultPlugins
                 .set(ImagePlugin::default_nearest())
                 .set(AssetPlugin {
                     meta_check: AssetMetaCheck::Never,
                     ..default()
                 }))
+        .add_plugins(PixelInputPlugin)
         .add_plugins(PixelCamPlugin)
         .add_plugins(PostProcessPlugin)
         .add_plugins(PlayerPlugin)
+        .add_plugins(PixelDebugPlugin)
+        .add_plugins(PixelStylePlugin)
+        .add_plugins(PixelToonPlugin)
+        .add_plugins(PixelShadowsPlugin)
+        .add_plugins(PixelBillboardPlugin)
+        .add_plugins(SteppedAnimationPlugin)
+        .add_plugins(PixelParticlesPlugin)
+        .add_plugins(PixelRecorderPlugin)
+        .add_plugins(PixelFontPlugin)
+        .add_plugins(PixelCursorPlugin)
+        .add_plugins(PixelViewPlugin)
         .add_systems(Startup, setup_mesh)
-        .add_systems(Update, (rotate_rotatable, rotate, update_settings))
+        .add_systems(PostStartup, (load_style, setup_hud))
+        .add_systems(Update, (rotate_rotatable, rotate, save_style, toggle_grid_movement, toggle_recording))
         .run();
 }
 
@@ -56,6 +67,8 @@ fn setup_mesh(
     mut commands: Commands,
     mut meshes: ResMut<Assets<Mesh>>,
     mut materials: ResMut<Assets<StandardMaterial>>,
+    mut toon_materials: ResMut<Assets<PixelToonMaterial>>,
+    mut images: ResMut<Assets<Image>>,
 ) {
     commands.spawn((
         Mesh3d(meshes.add(Cuboid::from_size(Vec3::new(1.,0.,1.)))),
@@ -64,29 +77,35 @@ fn setup_mesh(
         Rotatable{
             rotation: 0.
         },
+        StaticCollider,
+        SteppedAnimation::new(36.),
         PIXEL_PERFECT_LAYERS,
     )).with_children(|parent| {
         parent.spawn((
             Mesh3d(meshes.add(Mesh::from(Cuboid::from_size(Vec3::new(1.05,0.15,0.05))))),
             MeshMaterial3d(materials.add(Color::linear_rgb(0.5,0.5,0.5))),
+            StaticCollider,
             Transform::from_xyz(0., 0.075,0.5),
             PIXEL_PERFECT_LAYERS,
         ));
         parent.spawn((
             Mesh3d(meshes.add(Mesh::from(Cuboid::from_size(Vec3::new(1.05,0.15,0.05))))),
             MeshMaterial3d(materials.add(Color::linear_rgb(0.5,0.5,0.5))),
+            StaticCollider,
             Transform::from_xyz(0., 0.075,-0.5),
             PIXEL_PERFECT_LAYERS,
         ));
         parent.spawn((
             Mesh3d(meshes.add(Mesh::from(Cuboid::from_size(Vec3::new(0.05,0.15,1.05))))),
             MeshMaterial3d(materials.add(Color::linear_rgb(0.5,0.5,0.5))),
+            StaticCollider,
             Transform::from_xyz(0.5, 0.075,0.),
             PIXEL_PERFECT_LAYERS,
         ));
         parent.spawn((
             Mesh3d(meshes.add(Mesh::from(Cuboid::from_size(Vec3::new(0.05,0.15,1.05))))),
             MeshMaterial3d(materials.add(Color::linear_rgb(0.5,0.5,0.5))),
+            StaticCollider,
             Transform::from_xyz(-0.5, 0.075,0.),
             PIXEL_PERFECT_LAYERS,
         ));
@@ -94,22 +113,38 @@ fn setup_mesh(
 
     commands.spawn((
         Mesh3d(meshes.add(Capsule3d::new(0.5,1.0))),
-        MeshMaterial3d(materials.add(Color::linear_rgb(0.1,0.5,0.1))),
-        Transform::from_xyz(0., 8.,0.).with_scale(Vec3::splat(12.)),
+        MeshMaterial3d(toon_materials.add(PixelToonMaterial {
+            base: Color::linear_rgb(0.1,0.5,0.1).into(),
+            extension: PixelToonExtension {
+                dither_shadows: true,
+                ..default()
+            },
+        })),
+        Transform::from_xyz(0., 12.5,0.).with_scale(Vec3::splat(12.)),
         CameraTarget,
-        Player{
-            x: 0.,
-            y: 0.
-        },
+        Player,
         PIXEL_PERFECT_LAYERS,
     ));
 
+    commands.insert_resource(PixelCursorImage {
+        image: images.add(cursor_sprite()),
+        hotspot: UVec2::splat(3),
+    });
+    let tree = images.add(tree_sprite());
+    for position in [Vec3::new(25., 0., -30.), Vec3::new(-30., 0., 20.)] {
+        commands.spawn((
+            PixelBillboard::from_image(tree.clone()),
+            Transform::from_translation(position),
+        ));
+    }
+
     commands.spawn((
         DirectionalLight {
             shadows_enabled: true,
             illuminance: 4000.,
             ..default()
         },
+        PixelShadowLight,
         Transform::from_rotation(Quat::from_euler(
             EulerRot::ZYX,
             0.0,
@@ -118,6 +153,24 @@ fn setup_mesh(
         )),
     ));
 
+    // sparks rising from the green light
+    commands.spawn((
+        PixelParticles {
+            rate: 15.,
+            lifetime: 1.5,
+            area: Vec3::new(3., 0., 3.),
+            velocity: Vec3::Y * 8.,
+            spread: Vec3::new(2., 2., 2.),
+            colors: vec![
+                Color::srgb(0.8, 1.0, 0.6),
+                Color::srgb(0.4, 0.9, 0.3),
+                Color::srgb(0.1, 0.5, 0.1),
+            ],
+            ..default()
+        },
+        Transform::from_translation(Vec3::new(25.0, 2.0, 25.0)),
+    ));
+
     commands.spawn((
         Transform::from_translation(Vec3::new(25.0, 2.0, 25.0)),
         PointLight{
@@ -129,20 +182,208 @@ fn setup_mesh(
     ));
 }
 
-/// Rotates entities to demonstrate grid snapping.
+/// A 16x24 tree drawn in code, so the demo needs no image files.
+fn tree_sprite() -> Image {
+    let (width, height) = (16, 24);
+    let mut data = Vec::with_capacity(width * height * 4);
+    for y in 0..height {
+        for x in 0..width {
+            let dx = x as f32 - 7.5;
+            let dy = y as f32 - 8.;
+            let color = if dx * dx + dy * dy < 56. {
+                if (x + y) % 5 == 0 { [40, 110, 50, 255] } else { [60, 150, 60, 255] }
+            } else if y >= 14 && (6..10).contains(&x) {
+                [110, 70, 40, 255]
+            } else {
+                [0, 0, 0, 0]
+            };
+            data.extend_from_slice(&color);
+        }
+    }
+    Image::new(
+        Extent3d { width: width as u32, height: height as u32, ..default() },
+        TextureDimension::D2,
+        data,
+        TextureFormat::Rgba8UnormSrgb,
+        RenderAssetUsages::default(),
+    )
+}
+
+/// A 7x7 crosshair for the software cursor.
+fn cursor_sprite() -> Image {
+    let size = 7;
+    let mut data = Vec::with_capacity(size * size * 4);
+    for y in 0..size {
+        for x in 0..size {
+            // a plus with a hole in the middle
+            let color = if (x == 3) != (y == 3) { [255, 240, 200, 255] } else { [0, 0, 0, 0] };
+            data.extend_from_slice(&color);
+        }
+    }
+    Image::new(
+        Extent3d { width: size as u32, height: size as u32, ..default() },
+        TextureDimension::D2,
+        data,
+        TextureFormat::Rgba8UnormSrgb,
+        RenderAssetUsages::default(),
+    )
+}
+
+/// Takes the pixel camera's settings from a style file, which is hot-reloaded with the `file_watcher` feature,
+/// and gives it crisp shadows.
+fn load_style(
+    mut commands: Commands,
+    cam: Single<Entity, With<PixelCamera>>,
+    asset_server: Res<AssetServer>,
+) {
+    commands
+        .entity(*cam)
+        .insert((
+            PixelStyleHandle(asset_server.load("styles/default.pixelstyle.ron")),
+            PixelShadows::default(),
+        ));
+}
+
+/// A health bar, key hints and a top-down minimap drawn into the canvas, so their pixels match the game's.
+fn setup_hud(
+    mut commands: Commands,
+    ui_cam: Single<Entity, With<PixelUiCamera>>,
+    mut images: ResMut<Assets<Image>>,
+    asset_server: Res<AssetServer>,
+) {
+    let minimap = PixelView::new(&mut images, 64, 64);
+    commands.spawn((
+        Node {
+            position_type: PositionType::Absolute,
+            right: Val::Px(8.),
+            top: Val::Px(8.),
+            width: Val::Px(64.),
+            height: Val::Px(64.),
+            ..default()
+        },
+        ImageNode::new(minimap.image.clone()),
+        UiTargetCamera(*ui_cam),
+    ));
+    commands.spawn((
+        minimap,
+        Projection::from(OrthographicProjection {
+            // the 90 unit tray in 64 texels
+            scale: 1.5,
+            ..OrthographicProjection::default_3d()
+        }),
+        Transform::from_xyz(0., 200., 0.).looking_at(Vec3::ZERO, Vec3::NEG_Z),
+    ));
+
+    commands
+        .spawn((
+            Node {
+                position_type: PositionType::Absolute,
+                left: Val::Px(8.),
+                top: Val::Px(8.),
+                width: Val::Px(64.),
+                height: Val::Px(8.),
+                padding: UiRect::all(Val::Px(1.)),
+                border: UiRect::all(Val::Px(1.)),
+                ..default()
+            },
+            BorderColor(Color::srgb(0.9, 0.9, 0.8)),
+            BackgroundColor(Color::srgb(0.1, 0.1, 0.15)),
+            UiTargetCamera(*ui_cam),
+        ))
+        .with_child((
+            Node {
+                width: Val::Percent(75.),
+                height: Val::Percent(100.),
+                ..default()
+            },
+            BackgroundColor(Color::srgb(0.8, 0.2, 0.2)),
+        ));
+
+    // sprites on the pixel UI layers are placed from the canvas' centre, y up
+    let font = asset_server.load("fonts/tiny.pixelfont.ron");
+    commands.spawn((
+        PixelText {
+            text: "HP".into(),
+            font: font.clone(),
+            color: Color::srgb(0.9, 0.9, 0.8),
+            shadow: Some((IVec2::ONE, Color::srgb(0.1, 0.1, 0.15))),
+            ..default()
+        },
+        Transform::from_xyz(-(RES_WIDTH as f32) / 2. + 76., RES_HEIGHT as f32 / 2. - 9., 0.),
+        PIXEL_UI_LAYERS,
+    ));
+    commands.spawn((
+        PixelText {
+            text: "F9 RECORD   F5 SAVE STYLE   G GRID".into(),
+            font,
+            color: Color::srgb(0.9, 0.9, 0.8),
+            outline: Some(Color::srgb(0.1, 0.1, 0.15)),
+            ..default()
+        },
+        Transform::from_xyz(-(RES_WIDTH as f32) / 2. + 8., -(RES_HEIGHT as f32) / 2. + 14., 0.),
+        PIXEL_UI_LAYERS,
+    ));
+}
+
+/// Saves the current camera settings when F5 is pressed.
+fn save_style(
+    cam: Single<Entity, With<PixelCamera>>,
+    key_input: Res<ButtonInput<KeyCode>>,
+    mut save: EventWriter<SavePixelStyle>,
+) {
+    if key_input.just_pressed(KeyCode::F5) {
+        save.write(SavePixelStyle {
+            camera: *cam,
+            path: "assets/styles/saved.pixelstyle.ron".into(),
+        });
+    }
+}
+
+/// Switches the player between free and tile movement when G is pressed.
+fn toggle_grid_movement(
+    mut commands: Commands,
+    player: Single<(Entity, Has<GridMovement>), With<Player>>,
+    key_input: Res<ButtonInput<KeyCode>>,
+) {
+    if key_input.just_pressed(KeyCode::KeyG) {
+        let (entity, grid) = *player;
+        if grid {
+            commands.entity(entity).remove::<GridMovement>();
+        } else {
+            commands.entity(entity).insert(GridMovement::default());
+        }
+    }
+}
+
+/// Starts or stops recording the canvas to an APNG when F9 is pressed.
+fn toggle_recording(
+    recorder: Res<PixelRecorder>,
+    key_input: Res<ButtonInput<KeyCode>>,
+    mut start: EventWriter<StartPixelRecording>,
+    mut stop: EventWriter<StopPixelRecording>,
+) {
+    if key_input.just_pressed(KeyCode::F9) {
+        if recorder.is_recording() {
+            stop.write(StopPixelRecording);
+        } else {
+            start.write(StartPixelRecording::apng("recordings/clip.png").with_fps(30.).with_upscale(2));
+        }
+    }
+}
+
+/// Rotates entities in steps to demonstrate grid snapping.
 fn rotate(
     mut commands: Commands,
-    time: Res<Time>, 
-    mut transforms: Query<(Entity, &mut Transform, &mut Rotate), With<Rotate>>,
+    mut transforms: Query<(Entity, &mut Transform, &mut Rotate, &SteppedAnimation), With<Rotate>>,
     mut rotatables: Query<&mut Rotatable>
 ) {
-    for (e, mut transform, mut rotate) in &mut transforms {
+    for (e, mut transform, mut rotate, stepped) in &mut transforms {
         let mut y = 0.;
         if let Ok(rot) = rotatables.get(e)  {
             y = rot.rotation;
         }
-        rotate.timer.tick(time.delta());
-        let t = (rotate.timer.fraction() * 36.).round() / 36.;
+        rotate.timer.tick(stepped.delta());
+        let t = rotate.timer.fraction();
         transform.rotation = Quat::from_rotation_y(y*PI*0.5 + PI*0.5*t);
         if rotate.timer.finished(){
             if let Ok(mut rot) = rotatables.get_mut(e)  {
@@ -157,18 +398,3 @@ fn rotate(
     }
 }
 
-
-// Change the intensity over time to show that the effect is controlled from the main world
-fn update_settings(
-    mut settings: Query<&mut PostProcessSettings>,
-    keycode: Res<ButtonInput<KeyCode>>,
-    mut show_depth: ResMut<ShowSettings>,
-) {
-    if keycode.just_pressed(KeyCode::Space) {
-        for mut setting in &mut settings {
-            show_depth.value = (show_depth.value + 1) % 3;
-            setting.show_depth = (show_depth.value == 1) as u32;
-            setting.show_normals = (show_depth.value == 2) as u32;
-        }
-    }
-}
\ No newline at end of file
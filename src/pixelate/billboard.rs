@@ -0,0 +1,341 @@
+use std::f32::consts::TAU;
+
+use bevy::{
+    math::Affine2,
+    prelude::*,
+    render::view::VisibilitySystems,
+    sprite::Anchor,
+    transform::TransformSystem,
+};
+
+use crate::{PixelCamera, PIXEL_PERFECT_LAYERS, RES_WIDTH};
+
+/// A sprite in the pixel-perfect world that always faces the [`PixelCamera`].
+/// One sprite pixel covers one canvas texel, and its position is snapped to the texel grid so it stays crisp.
+/// It is drawn as an alpha-masked quad, so it is depth-tested against the scene and gets outlines from the prepass.
+/// The entity's rotation and scale are ignored.
+#[derive(Component, Reflect, Clone, Debug)]
+#[reflect(Component, Default)]
+#[require(Transform, Visibility)]
+pub struct PixelBillboard {
+    pub image: Handle<Image>,
+    /// Frame of the image to show, the whole image if unset.
+    pub texture_atlas: Option<TextureAtlas>,
+    pub flip_x: bool,
+    /// Point of the sprite placed at the entity's position. Defaults to its feet.
+    pub anchor: Anchor,
+}
+
+impl Default for PixelBillboard {
+    fn default() -> Self {
+        Self {
+            image: default(),
+            texture_atlas: None,
+            flip_x: false,
+            anchor: Anchor::BottomCenter,
+        }
+    }
+}
+
+impl PixelBillboard {
+    pub fn from_image(image: Handle<Image>) -> Self {
+        Self {
+            image,
+            ..default()
+        }
+    }
+
+    pub fn from_atlas_image(image: Handle<Image>, atlas: TextureAtlas) -> Self {
+        Self {
+            image,
+            texture_atlas: Some(atlas),
+            ..default()
+        }
+    }
+}
+
+/// Picks the [`PixelBillboard`]'s atlas row from the angle between the entity's facing and the [`PixelCamera`],
+/// for characters drawn from several sides. The entity faces along its forward (-Z) axis.
+///
+/// The atlas is a grid with one row per direction and [`frames`](Self::frames) columns of animation.
+/// Row 0 shows the front, facing the camera, and the following rows step counter-clockwise seen from above.
+/// With [`mirror`](Self::mirror) only the rows from the front to the back are stored, `directions / 2 + 1` of them,
+/// and the other side reuses them flipped.
+#[derive(Component, Reflect, Clone, Copy, Debug)]
+#[reflect(Component, Default)]
+#[require(PixelBillboard)]
+pub struct DirectionalSprite {
+    /// Number of directions around the entity, usually 4, 8 or 16.
+    pub directions: u32,
+    pub mirror: bool,
+    /// Animation frames per row.
+    pub frames: usize,
+    /// Current animation frame, the column in the row.
+    pub frame: usize,
+}
+
+impl Default for DirectionalSprite {
+    fn default() -> Self {
+        Self {
+            directions: 8,
+            mirror: false,
+            frames: 1,
+            frame: 0,
+        }
+    }
+}
+
+impl DirectionalSprite {
+    pub fn new(directions: u32, frames: usize) -> Self {
+        Self {
+            directions,
+            frames,
+            ..default()
+        }
+    }
+
+    pub fn mirrored(mut self) -> Self {
+        self.mirror = true;
+        self
+    }
+
+    /// Atlas row and whether to flip it for a facing `angle` in radians,
+    /// measured counter-clockwise from the direction towards the camera.
+    pub fn row(&self, angle: f32) -> (usize, bool) {
+        let directions = self.directions.max(1);
+        let sector = (angle / TAU * directions as f32).round().rem_euclid(directions as f32) as u32;
+        if self.mirror && sector > directions / 2 {
+            ((directions - sector) as usize, true)
+        } else {
+            (sector as usize, false)
+        }
+    }
+}
+
+/// Quad that draws a [`PixelBillboard`], spawned as its child.
+#[derive(Component)]
+struct BillboardQuad(Entity);
+
+/// Unit quad with its origin in the bottom-left corner, shared by all billboards.
+#[derive(Resource)]
+struct BillboardMesh(Handle<Mesh>);
+
+impl FromWorld for BillboardMesh {
+    fn from_world(world: &mut World) -> Self {
+        let mesh = Mesh::from(Rectangle::new(1., 1.)).translated_by(Vec3::new(0.5, 0.5, 0.));
+        Self(world.resource_mut::<Assets<Mesh>>().add(mesh))
+    }
+}
+
+pub struct PixelBillboardPlugin;
+
+impl Plugin for PixelBillboardPlugin {
+    fn build(&self, app: &mut App) {
+        app
+        .register_type::<PixelBillboard>()
+        .register_type::<DirectionalSprite>()
+        .init_resource::<BillboardMesh>()
+        .add_systems(Update, (select_sprite_directions, spawn_billboard_quads, update_billboard_materials).chain())
+        .add_systems(PostUpdate, orient_billboards
+            .after(TransformSystem::TransformPropagate)
+            .before(VisibilitySystems::CheckVisibility));
+    }
+}
+
+fn spawn_billboard_quads(
+    mut commands: Commands,
+    mesh: Res<BillboardMesh>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    billboards: Query<(Entity, &PixelBillboard), Without<BillboardQuad>>,
+){
+    for (entity, billboard) in &billboards {
+        let material = materials.add(StandardMaterial {
+            base_color_texture: Some(billboard.image.clone()),
+            alpha_mode: AlphaMode::Mask(0.5),
+            unlit: true,
+            ..default()
+        });
+        let quad = commands.spawn((
+            Mesh3d(mesh.0.clone()),
+            MeshMaterial3d(material),
+            PIXEL_PERFECT_LAYERS,
+            ChildOf(entity),
+        )).id();
+        commands.entity(entity).insert(BillboardQuad(quad));
+    }
+}
+
+/// Sets the atlas frame and flip of [`DirectionalSprite`]s from where the camera looks at them.
+fn select_sprite_directions(
+    cam: Single<&Transform, With<PixelCamera>>,
+    mut sprites: Query<(&DirectionalSprite, &mut PixelBillboard, &GlobalTransform)>,
+){
+    // direction towards the camera on the ground plane
+    let to_cam = cam.back().with_y(0.).normalize_or_zero();
+    if to_cam == Vec3::ZERO {
+        return;
+    }
+    for (sprite, mut billboard, transform) in &mut sprites {
+        let facing = transform.forward().with_y(0.).normalize_or_zero();
+        let angle = to_cam.cross(facing).y.atan2(to_cam.dot(facing));
+        let (row, flip) = sprite.row(angle);
+        let index = row * sprite.frames + sprite.frame.min(sprite.frames.saturating_sub(1));
+
+        let Some(atlas) = billboard.texture_atlas.as_ref() else {
+            continue;
+        };
+        if atlas.index != index || billboard.flip_x != flip {
+            billboard.flip_x = flip;
+            if let Some(atlas) = billboard.texture_atlas.as_mut() {
+                atlas.index = index;
+            }
+        }
+    }
+}
+
+/// Points each quad's material at the billboard's image and frame.
+/// Runs every frame, as images and atlas layouts may finish loading after the billboard is spawned.
+fn update_billboard_materials(
+    billboards: Query<(&PixelBillboard, &BillboardQuad)>,
+    quads: Query<&MeshMaterial3d<StandardMaterial>>,
+    images: Res<Assets<Image>>,
+    layouts: Res<Assets<TextureAtlasLayout>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+){
+    for (billboard, quad) in &billboards {
+        let Ok(handle) = quads.get(quad.0) else {
+            continue;
+        };
+        let Some(material) = materials.get(handle) else {
+            continue;
+        };
+        let uv_transform = images.get(&billboard.image).map_or(Affine2::IDENTITY, |image| {
+            let size = image.size().as_vec2();
+            let rect = frame_rect(billboard, image, &layouts).as_rect();
+            let frame = Affine2::from_scale_angle_translation(rect.size() / size, 0., rect.min / size);
+            if billboard.flip_x {
+                frame * Affine2::from_scale_angle_translation(Vec2::new(-1., 1.), 0., Vec2::X)
+            } else {
+                frame
+            }
+        });
+        // only touch the asset when something changed, so it isn't re-uploaded every frame
+        if material.base_color_texture.as_ref() == Some(&billboard.image) && material.uv_transform == uv_transform {
+            continue;
+        }
+        if let Some(material) = materials.get_mut(handle) {
+            material.base_color_texture = Some(billboard.image.clone());
+            material.uv_transform = uv_transform;
+        }
+    }
+}
+
+/// Part of the image the billboard shows, in pixels.
+fn frame_rect(billboard: &PixelBillboard, image: &Image, layouts: &Assets<TextureAtlasLayout>) -> URect {
+    billboard
+        .texture_atlas
+        .as_ref()
+        .and_then(|atlas| atlas.texture_rect(layouts))
+        .unwrap_or(URect::from_corners(UVec2::ZERO, image.size()))
+}
+
+/// Turns the quads towards the camera and snaps them to the canvas texels.
+fn orient_billboards(
+    cam: Single<(&GlobalTransform, &Projection), With<PixelCamera>>,
+    billboards: Query<(&PixelBillboard, &BillboardQuad, &GlobalTransform), Without<PixelCamera>>,
+    mut quads: Query<&mut GlobalTransform, (Without<PixelBillboard>, Without<PixelCamera>)>,
+    images: Res<Assets<Image>>,
+    layouts: Res<Assets<TextureAtlasLayout>>,
+){
+    let (cam_t, projection) = cam.into_inner();
+    let Projection::Orthographic(ortho) = projection else {
+        return;
+    };
+    let texel = ortho.area.width() / RES_WIDTH as f32;
+    if texel <= 0. {
+        return;
+    }
+    let rotation = cam_t.rotation();
+    let (right, up, back) = (rotation * Vec3::X, rotation * Vec3::Y, rotation * Vec3::Z);
+
+    for (billboard, quad, transform) in &billboards {
+        let Ok(mut quad_t) = quads.get_mut(quad.0) else {
+            continue;
+        };
+        let Some(image) = images.get(&billboard.image) else {
+            continue;
+        };
+        let size = frame_rect(billboard, image, &layouts).size().as_vec2();
+
+        // the camera sits on the texel grid, so snapping in its plane lines the sprite up with the canvas
+        let position = transform.translation();
+        let snapped = (Vec2::new(position.dot(right), position.dot(up)) / texel).round();
+        let offset = ((billboard.anchor.as_vec() + 0.5) * size).round();
+        let corner = (snapped - offset) * texel;
+
+        *quad_t = GlobalTransform::from(Transform {
+            translation: right * corner.x + up * corner.y + back * position.dot(back),
+            rotation,
+            scale: (size * texel).extend(1.),
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::{FRAC_PI_2, FRAC_PI_4, PI};
+
+    use super::*;
+
+    fn sprite(directions: u32, mirror: bool) -> DirectionalSprite {
+        DirectionalSprite {
+            directions,
+            mirror,
+            ..default()
+        }
+    }
+
+    #[test]
+    fn four_directions() {
+        let rows = [0., FRAC_PI_2, PI, -FRAC_PI_2].map(|angle| sprite(4, false).row(angle));
+        assert_eq!(rows, [(0, false), (1, false), (2, false), (3, false)]);
+        let rows = [0., FRAC_PI_2, PI, -FRAC_PI_2].map(|angle| sprite(4, true).row(angle));
+        assert_eq!(rows, [(0, false), (1, false), (2, false), (1, true)]);
+    }
+
+    #[test]
+    fn eight_directions() {
+        assert_eq!(sprite(8, false).row(FRAC_PI_4), (1, false));
+        assert_eq!(sprite(8, false).row(-FRAC_PI_4), (7, false));
+        assert_eq!(sprite(8, true).row(-FRAC_PI_4), (1, true));
+        assert_eq!(sprite(8, true).row(-3. * FRAC_PI_4), (3, true));
+        // rounds to the nearest direction
+        assert_eq!(sprite(8, false).row(FRAC_PI_4 + 0.3), (1, false));
+        assert_eq!(sprite(8, false).row(FRAC_PI_4 + 0.5), (2, false));
+    }
+
+    #[test]
+    fn sixteen_directions() {
+        let sector = TAU / 16.;
+        assert_eq!(sprite(16, false).row(5. * sector), (5, false));
+        assert_eq!(sprite(16, false).row(-5. * sector), (11, false));
+        assert_eq!(sprite(16, true).row(-5. * sector), (5, true));
+        assert_eq!(sprite(16, true).row(8. * sector), (8, false));
+    }
+
+    #[test]
+    fn angles_wrap_around() {
+        for directions in [4, 8, 16] {
+            for mirror in [false, true] {
+                let sprite = sprite(directions, mirror);
+                let back = (directions as usize / 2, false);
+                assert_eq!(sprite.row(PI), back);
+                assert_eq!(sprite.row(-PI), back);
+                assert_eq!(sprite.row(PI - 0.01), back);
+                assert_eq!(sprite.row(-PI + 0.01), back);
+                assert_eq!(sprite.row(TAU - 0.01), (0, false));
+                assert_eq!(sprite.row(TAU + FRAC_PI_2), sprite.row(FRAC_PI_2));
+            }
+        }
+    }
+}
@@ -0,0 +1,181 @@
+use bevy::{
+    prelude::*,
+    sprite::Anchor,
+    transform::TransformSystem,
+    window::PrimaryWindow,
+};
+
+use crate::{Canvas, PixelCamSettings, WindowSize, HIGH_RES_LAYERS, RES_HEIGHT, RES_WIDTH};
+
+/// Where the mouse is on the canvas, updated every frame by [`PixelCursorPlugin`].
+#[derive(Resource, Reflect, Default, Debug)]
+#[reflect(Resource, Default)]
+pub struct PixelCursor {
+    /// In canvas texels from the top-left corner, with the fraction within the texel.
+    /// `None` when the cursor is outside the window or on the letterbox around the canvas.
+    /// This is the [`PixelCamera`](crate::PixelCamera)'s viewport, so it can be passed to `Camera::viewport_to_world`.
+    pub position: Option<Vec2>,
+}
+
+impl PixelCursor {
+    /// The texel under the cursor.
+    pub fn texel(&self) -> Option<UVec2> {
+        self.position.map(|position| position.floor().as_uvec2())
+    }
+}
+
+/// Draws `image` as the mouse cursor at canvas resolution, snapped to texels, and hides the
+/// system cursor over the canvas. Remove the resource to get the system cursor back.
+#[derive(Resource, Reflect, Clone, Debug)]
+#[reflect(Resource)]
+pub struct PixelCursorImage {
+    pub image: Handle<Image>,
+    /// The texel of `image` that points at the cursor position.
+    pub hotspot: UVec2,
+}
+
+/// The sprite drawn for [`PixelCursorImage`].
+#[derive(Component)]
+struct SoftwareCursor;
+
+pub struct PixelCursorPlugin;
+
+impl Plugin for PixelCursorPlugin {
+    fn build(&self, app: &mut App) {
+        app
+        .register_type::<PixelCursor>()
+        .register_type::<PixelCursorImage>()
+        .init_resource::<PixelCursor>()
+        // the canvas has been moved for this frame by then
+        .add_systems(PostUpdate, (update_pixel_cursor, draw_software_cursor).chain().before(TransformSystem::TransformPropagate));
+    }
+}
+
+/// Maps a cursor position in the window to canvas texels, or `None` outside the canvas.
+///
+/// The canvas is centered in a `window` sized window, scaled by `texel_size` and shifted by
+/// `canvas_offset` (in texels, y up) for smooth scrolling.
+pub fn window_to_canvas(cursor: Vec2, window: Vec2, texel_size: f32, canvas_offset: Vec2) -> Option<Vec2> {
+    if texel_size <= 0. {
+        return None;
+    }
+    let canvas_size = Vec2::new(RES_WIDTH as f32, RES_HEIGHT as f32);
+    let from_center = (cursor - window / 2.) / texel_size - canvas_offset * Vec2::new(1., -1.);
+    let position = from_center + canvas_size / 2.;
+    let inside = position.cmpge(Vec2::ZERO).all() && position.cmplt(canvas_size).all();
+    inside.then_some(position)
+}
+
+fn update_pixel_cursor(
+    mut cursor: ResMut<PixelCursor>,
+    window: Option<Single<&Window, With<PrimaryWindow>>>,
+    window_size: Res<WindowSize>,
+    canvas: Option<Single<&Transform, With<Canvas>>>,
+) {
+    let (Some(window), Some(canvas)) = (window, canvas) else {
+        cursor.position = None;
+        return;
+    };
+    cursor.position = window.cursor_position().and_then(|position| {
+        window_to_canvas(
+            position,
+            Vec2::new(window.width(), window.height()),
+            window_size.texel_size,
+            canvas.translation.truncate(),
+        )
+    });
+}
+
+#[allow(clippy::type_complexity)]
+fn draw_software_cursor(
+    mut commands: Commands,
+    cursor: Res<PixelCursor>,
+    cursor_image: Option<Res<PixelCursorImage>>,
+    settings: Res<PixelCamSettings>,
+    mut sprite: Option<Single<(Entity, &mut Sprite, &mut Transform, &mut Visibility), With<SoftwareCursor>>>,
+    canvas: Option<Single<&Transform, (With<Canvas>, Without<SoftwareCursor>)>>,
+    window: Option<Single<&mut Window, With<PrimaryWindow>>>,
+) {
+    let Some(cursor_image) = cursor_image.filter(|_| settings.outer_camera) else {
+        if let Some(sprite) = sprite {
+            commands.entity(sprite.0).despawn();
+            if let Some(mut window) = window {
+                window.cursor_options.visible = true;
+            }
+        }
+        return;
+    };
+
+    let Some((_, image, transform, visibility)) = sprite.as_deref_mut() else {
+        commands.spawn((
+            Sprite {
+                image: cursor_image.image.clone(),
+                anchor: Anchor::TopLeft,
+                ..default()
+            },
+            Transform::default(),
+            Visibility::Hidden,
+            SoftwareCursor,
+            HIGH_RES_LAYERS,
+        ));
+        return;
+    };
+    if image.image != cursor_image.image {
+        image.image = cursor_image.image.clone();
+    }
+
+    if let Some(mut window) = window {
+        let visible = cursor.position.is_none();
+        if window.cursor_options.visible != visible {
+            window.cursor_options.visible = visible;
+        }
+    }
+    let (Some(texel), Some(canvas)) = (cursor.texel(), canvas) else {
+        visibility.set_if_neq(Visibility::Hidden);
+        return;
+    };
+    visibility.set_if_neq(Visibility::Inherited);
+
+    // back from the canvas' top-left texel to the outer camera, where one unit is one texel
+    let corner = (texel.as_ivec2() - cursor_image.hotspot.as_ivec2()).as_vec2();
+    let half_canvas = Vec2::new(RES_WIDTH as f32, RES_HEIGHT as f32) / 2.;
+    let position = Vec2::new(corner.x - half_canvas.x, half_canvas.y - corner.y) + canvas.translation.truncate();
+    transform.translation = position.extend(10.);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 2000x1080 at 3x leaves a 40 pixel letterbox left and right of the 1920x1080 canvas.
+    const WINDOW: Vec2 = Vec2::new(2000., 1080.);
+
+    #[test]
+    fn letterbox_is_outside_the_canvas() {
+        assert_eq!(window_to_canvas(Vec2::new(20., 540.), WINDOW, 3., Vec2::ZERO), None);
+        assert_eq!(window_to_canvas(Vec2::new(1980., 540.), WINDOW, 3., Vec2::ZERO), None);
+        assert_eq!(window_to_canvas(Vec2::new(1960., 540.), WINDOW, 3., Vec2::ZERO), None);
+    }
+
+    #[test]
+    fn corners_map_to_corner_texels() {
+        let top_left = window_to_canvas(Vec2::new(40., 0.), WINDOW, 3., Vec2::ZERO);
+        assert_eq!(top_left, Some(Vec2::ZERO));
+        let bottom_right = window_to_canvas(Vec2::new(1959.5, 1079.5), WINDOW, 3., Vec2::ZERO);
+        let texel = PixelCursor{position: bottom_right}.texel();
+        assert_eq!(texel, Some(UVec2::new(RES_WIDTH - 1, RES_HEIGHT - 1)));
+    }
+
+    #[test]
+    fn canvas_offset_moves_the_texels() {
+        // the canvas is half a texel right and a quarter up, so the window's left edge is off it
+        let offset = Vec2::new(0.5, 0.25);
+        assert_eq!(window_to_canvas(Vec2::new(40., 0.), WINDOW, 3., offset), None);
+        assert_eq!(window_to_canvas(Vec2::new(41.5, 0.), WINDOW, 3., offset), Some(Vec2::new(0., 0.25)));
+    }
+
+    #[test]
+    fn zero_texel_size_has_no_canvas() {
+        assert_eq!(window_to_canvas(Vec2::new(1000., 540.), WINDOW, 0., Vec2::ZERO), None);
+    }
+}
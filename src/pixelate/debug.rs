@@ -0,0 +1,25 @@
+use bevy::prelude::*;
+
+use crate::{PixelAction, PostProcessSettings};
+
+/// Optional plugin that cycles every camera's [`PixelDebugView`](crate::PixelDebugView) on [`PixelAction::CycleDebugView`].
+/// Needs the [`PixelInputPlugin`](crate::PixelInputPlugin).
+pub struct PixelDebugPlugin;
+
+impl Plugin for PixelDebugPlugin {
+    fn build(&self, app: &mut App) {
+        app
+        .add_systems(Update, cycle_debug_view.run_if(resource_exists::<ButtonInput<PixelAction>>));
+    }
+}
+
+fn cycle_debug_view(
+    mut settings: Query<&mut PostProcessSettings>,
+    actions: Res<ButtonInput<PixelAction>>,
+) {
+    if actions.just_pressed(PixelAction::CycleDebugView) {
+        for mut setting in &mut settings {
+            setting.debug_view = setting.debug_view.next();
+        }
+    }
+}
@@ -0,0 +1,602 @@
+use bevy::{
+    asset::{io::Reader, AssetLoader, LoadContext, RenderAssetUsages},
+    image::ImageSampler,
+    platform::collections::{HashMap, HashSet},
+    prelude::*,
+    render::render_resource::{Extent3d, TextureDimension, TextureFormat},
+    sprite::Anchor,
+};
+use image::RgbaImage;
+use serde::Deserialize;
+use thiserror::Error;
+
+use crate::{postprocessing::quantize_linear, PixelCamera, PostProcessSettings};
+
+/// A bitmap font: glyphs cut from one image, drawn texel for texel by [`PixelText`].
+///
+/// Loaded from AngelCode BMFont files in the text format (`.fnt`, one page), or from a
+/// `.pixelfont.ron` file describing a grid of equally sized glyphs in a PNG.
+#[derive(Asset, TypePath, Clone, Debug)]
+pub struct PixelFont {
+    pub image: Handle<Image>,
+    /// Distance between the tops of two lines, in texels.
+    pub line_height: u32,
+    pub glyphs: HashMap<char, PixelGlyph>,
+    /// Added to the advance between a pair of characters.
+    pub kerning: HashMap<(char, char), i32>,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct PixelGlyph {
+    /// Where the glyph is in the font image.
+    pub rect: URect,
+    /// From the pen position (top of the line) to the glyph's top-left corner.
+    pub offset: IVec2,
+    /// How far the pen moves after the glyph.
+    pub advance: i32,
+}
+
+/// Pixel-perfect text drawn with a [`PixelFont`] into the entity's [`Sprite`].
+///
+/// Glyphs are placed at whole texels, and the sprite is anchored at its top-left corner, so
+/// keep the translation whole too. Put it on [`PIXEL_UI_LAYERS`](crate::PIXEL_UI_LAYERS) for
+/// text in the canvas, or on [`HIGH_RES_LAYERS`](crate::HIGH_RES_LAYERS) to draw it over the
+/// upscaled canvas. One unit there is already one canvas texel, so `scale` 1 matches the game's
+/// texels and larger scales make blockier text.
+/// Colours are snapped to the pixel camera's palette when it has one.
+#[derive(Component, Reflect, Clone, Debug)]
+#[reflect(Component, Default)]
+#[require(Sprite, Transform, Visibility)]
+pub struct PixelText {
+    pub text: String,
+    pub font: Handle<PixelFont>,
+    pub color: Color,
+    /// One-texel outline around the glyphs.
+    pub outline: Option<Color>,
+    /// Drop shadow, offset in texels (positive y is down).
+    pub shadow: Option<(IVec2, Color)>,
+    /// Wraps lines at spaces to fit this many texels.
+    pub max_width: Option<u32>,
+    pub align: PixelTextAlign,
+    /// Whole-number size multiplier.
+    pub scale: u32,
+}
+
+impl Default for PixelText {
+    fn default() -> Self {
+        Self {
+            text: String::new(),
+            font: Handle::default(),
+            color: Color::WHITE,
+            outline: None,
+            shadow: None,
+            max_width: None,
+            align: PixelTextAlign::Left,
+            scale: 1,
+        }
+    }
+}
+
+impl PixelText {
+    pub fn new(text: impl Into<String>, font: Handle<PixelFont>) -> Self {
+        Self {
+            text: text.into(),
+            font,
+            ..default()
+        }
+    }
+}
+
+/// How lines are aligned within the widest line.
+#[derive(Reflect, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PixelTextAlign {
+    #[default]
+    Left,
+    Center,
+    Right,
+}
+
+#[derive(Debug, Error)]
+pub enum PixelFontLoaderError {
+    #[error("could not read pixel font: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("could not parse pixel font: {0}")]
+    Ron(#[from] ron::error::SpannedError),
+    #[error("could not parse BMFont file: {0}")]
+    BmFont(String),
+    #[error("invalid pixel font image path: {0}")]
+    ImagePath(String),
+}
+
+/// Loads AngelCode BMFont `.fnt` files in the text format.
+#[derive(Default)]
+pub struct BmFontLoader;
+
+impl AssetLoader for BmFontLoader {
+    type Asset = PixelFont;
+    type Settings = ();
+    type Error = PixelFontLoaderError;
+
+    async fn load(
+        &self,
+        reader: &mut dyn Reader,
+        _settings: &(),
+        load_context: &mut LoadContext<'_>,
+    ) -> Result<PixelFont, PixelFontLoaderError> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).await?;
+        let text = String::from_utf8(bytes).map_err(|e| PixelFontLoaderError::BmFont(e.to_string()))?;
+        let mut line_height = 0;
+        let mut page = None;
+        let mut glyphs = HashMap::default();
+        let mut kerning = HashMap::default();
+
+        for line in text.lines() {
+            let (tag, fields) = bmfont_fields(line);
+            let int = |key: &str| -> Result<i32, PixelFontLoaderError> {
+                fields
+                    .iter()
+                    .find(|(k, _)| *k == key)
+                    .and_then(|(_, value)| value.parse().ok())
+                    .ok_or_else(|| PixelFontLoaderError::BmFont(format!("missing `{key}` in `{line}`")))
+            };
+            match tag {
+                "common" => {
+                    line_height = int("lineHeight")?.max(0) as u32;
+                    if int("pages").unwrap_or(1) > 1 {
+                        return Err(PixelFontLoaderError::BmFont("only single-page fonts are supported".into()));
+                    }
+                }
+                "page" => {
+                    page = fields.iter().find(|(k, _)| *k == "file").map(|(_, file)| file.to_string());
+                }
+                "char" => {
+                    let Some(c) = char::from_u32(int("id")? as u32) else {
+                        continue;
+                    };
+                    let (x, y) = (int("x")?.max(0) as u32, int("y")?.max(0) as u32);
+                    let (width, height) = (int("width")?.max(0) as u32, int("height")?.max(0) as u32);
+                    glyphs.insert(c, PixelGlyph {
+                        rect: URect::new(x, y, x + width, y + height),
+                        offset: IVec2::new(int("xoffset")?, int("yoffset")?),
+                        advance: int("xadvance")?,
+                    });
+                }
+                "kerning" => {
+                    if let (Some(first), Some(second)) = (char::from_u32(int("first")? as u32), char::from_u32(int("second")? as u32)) {
+                        kerning.insert((first, second), int("amount")?);
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        let page = page.ok_or_else(|| PixelFontLoaderError::BmFont("no page image".into()))?;
+        let path = load_context
+            .asset_path()
+            .resolve_embed(&page)
+            .map_err(|e| PixelFontLoaderError::ImagePath(e.to_string()))?;
+        Ok(PixelFont {
+            image: load_context.load(path),
+            line_height,
+            glyphs,
+            kerning,
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["fnt"]
+    }
+}
+
+/// Splits a BMFont line like `char id=65 x=0 ...` into its tag and `key=value` pairs.
+fn bmfont_fields(line: &str) -> (&str, Vec<(&str, &str)>) {
+    let line = line.trim();
+    let (tag, mut rest) = line.split_once(' ').unwrap_or((line, ""));
+    let mut fields = Vec::new();
+    while let Some((key, after)) = rest.trim_start().split_once('=') {
+        let (value, remaining) = match after.strip_prefix('"') {
+            Some(quoted) => quoted.split_once('"').unwrap_or((quoted, "")),
+            None => after.split_once(' ').unwrap_or((after, "")),
+        };
+        fields.push((key, value));
+        rest = remaining;
+    }
+    (tag, fields)
+}
+
+/// A `.pixelfont.ron` file: a PNG with glyphs in a grid, in the order of `chars`.
+#[derive(Deserialize)]
+struct GridFont {
+    /// Path of the PNG, relative to the `.pixelfont.ron` file.
+    image: String,
+    /// Size of a grid cell in texels.
+    glyph_size: (u32, u32),
+    /// Cells per row.
+    columns: u32,
+    chars: String,
+    /// Pen advance per glyph, the cell width by default.
+    #[serde(default)]
+    advance: Option<u32>,
+    /// The cell height by default.
+    #[serde(default)]
+    line_height: Option<u32>,
+}
+
+/// Loads `.pixelfont.ron` grid fonts.
+#[derive(Default)]
+pub struct GridFontLoader;
+
+impl AssetLoader for GridFontLoader {
+    type Asset = PixelFont;
+    type Settings = ();
+    type Error = PixelFontLoaderError;
+
+    async fn load(
+        &self,
+        reader: &mut dyn Reader,
+        _settings: &(),
+        load_context: &mut LoadContext<'_>,
+    ) -> Result<PixelFont, PixelFontLoaderError> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).await?;
+        let grid: GridFont = ron::de::from_bytes(&bytes)?;
+        let (width, height) = grid.glyph_size;
+        let columns = grid.columns.max(1);
+        let glyphs = grid
+            .chars
+            .chars()
+            .enumerate()
+            .map(|(i, c)| {
+                let corner = UVec2::new(i as u32 % columns, i as u32 / columns) * UVec2::new(width, height);
+                (c, PixelGlyph {
+                    rect: URect::from_corners(corner, corner + UVec2::new(width, height)),
+                    offset: IVec2::ZERO,
+                    advance: grid.advance.unwrap_or(width) as i32,
+                })
+            })
+            .collect();
+        let path = load_context
+            .asset_path()
+            .resolve_embed(&grid.image)
+            .map_err(|e| PixelFontLoaderError::ImagePath(e.to_string()))?;
+        Ok(PixelFont {
+            image: load_context.load(path),
+            line_height: grid.line_height.unwrap_or(height),
+            glyphs,
+            kerning: HashMap::default(),
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["pixelfont.ron"]
+    }
+}
+
+pub struct PixelFontPlugin;
+
+impl Plugin for PixelFontPlugin {
+    fn build(&self, app: &mut App) {
+        app
+        .init_asset::<PixelFont>()
+        .register_type::<PixelText>()
+        .register_type::<PixelTextAlign>()
+        .init_asset_loader::<BmFontLoader>()
+        .init_asset_loader::<GridFontLoader>()
+        .add_systems(PostUpdate, draw_pixel_text.before(bevy::sprite::calculate_bounds_2d));
+    }
+}
+
+/// Redraws the image of every [`PixelText`] that changed, or whose font just finished loading.
+fn draw_pixel_text(
+    mut texts: Query<(Ref<PixelText>, &mut Sprite)>,
+    mut font_events: EventReader<AssetEvent<PixelFont>>,
+    mut image_events: EventReader<AssetEvent<Image>>,
+    fonts: Res<Assets<PixelFont>>,
+    mut images: ResMut<Assets<Image>>,
+    post_process: Option<Single<Ref<PostProcessSettings>, With<PixelCamera>>>,
+) {
+    let loaded_images: HashSet<AssetId<Image>> = image_events
+        .read()
+        .filter_map(|event| match event {
+            AssetEvent::LoadedWithDependencies { id } | AssetEvent::Modified { id } => Some(*id),
+            _ => None,
+        })
+        .collect();
+    let loaded_fonts: HashSet<AssetId<PixelFont>> = font_events
+        .read()
+        .filter_map(|event| match event {
+            AssetEvent::LoadedWithDependencies { id } | AssetEvent::Modified { id } => Some(*id),
+            _ => None,
+        })
+        .collect();
+    let palette_changed = post_process.as_ref().is_some_and(|settings| settings.is_changed());
+    let levels = post_process.as_ref().and_then(|settings| settings.palette_levels());
+
+    for (text, mut sprite) in &mut texts {
+        let Some(font) = fonts.get(&text.font) else {
+            continue;
+        };
+        let font_loaded = loaded_fonts.contains(&text.font.id()) || loaded_images.contains(&font.image.id());
+        if !text.is_changed() && !font_loaded && !palette_changed {
+            continue;
+        }
+        let Some(atlas) = images.get(&font.image).and_then(|image| image.clone().try_into_dynamic().ok()) else {
+            continue;
+        };
+
+        let rendered = render_text(&text, font, &atlas.to_rgba8(), levels);
+        let size = Extent3d {
+            width: rendered.width().max(1),
+            height: rendered.height().max(1),
+            ..default()
+        };
+        let mut data = rendered.into_raw();
+        data.resize((size.width * size.height * 4) as usize, 0);
+        let mut image = Image::new(size, TextureDimension::D2, data, TextureFormat::Rgba8UnormSrgb, RenderAssetUsages::RENDER_WORLD);
+        image.sampler = ImageSampler::nearest();
+
+        // reuse the text's own image rather than leaking one per edit
+        if sprite.image == Handle::default() {
+            sprite.image = images.add(image);
+        } else {
+            images.insert(&sprite.image, image);
+        }
+        sprite.custom_size = Some(Vec2::new(size.width as f32, size.height as f32) * text.scale.max(1) as f32);
+        sprite.anchor = Anchor::TopLeft;
+    }
+}
+
+/// Lays out and draws `text` at 1x, with its shadow and outline.
+fn render_text(text: &PixelText, font: &PixelFont, atlas: &RgbaImage, levels: Option<f32>) -> RgbaImage {
+    let lines = layout_lines(&text.text, font, text.max_width);
+    let widest = lines.iter().map(|(_, width)| *width).max().unwrap_or(0);
+
+    // glyph pen positions, aligned per line
+    let mut placed = Vec::new();
+    for (row, (line, width)) in lines.iter().enumerate() {
+        let mut pen = IVec2::new(
+            match text.align {
+                PixelTextAlign::Left => 0,
+                PixelTextAlign::Center => (widest - width) / 2,
+                PixelTextAlign::Right => widest - width,
+            },
+            (row as u32 * font.line_height) as i32,
+        );
+        let mut previous = None;
+        for c in line.chars() {
+            let Some(glyph) = font.glyphs.get(&c) else {
+                continue;
+            };
+            if let Some(previous) = previous {
+                pen.x += font.kerning.get(&(previous, c)).copied().unwrap_or(0);
+            }
+            placed.push((pen + glyph.offset, glyph.rect));
+            pen.x += glyph.advance;
+            previous = Some(c);
+        }
+    }
+
+    // the coverage of the glyphs, with room for the outline and shadow
+    let shadow = text.shadow.map(|(offset, _)| offset).unwrap_or_default();
+    let border = text.outline.is_some() as i32;
+    // glyphs with a negative offset reach left of or above the pen
+    let min = placed.iter().map(|(corner, _)| *corner).fold(IVec2::ZERO, IVec2::min)
+        - IVec2::splat(border)
+        + shadow.min(IVec2::ZERO);
+    let max = placed
+        .iter()
+        .map(|(corner, rect)| *corner + rect.size().as_ivec2())
+        .fold(IVec2::new(widest, (lines.len() as u32 * font.line_height) as i32), IVec2::max)
+        + IVec2::splat(border)
+        + shadow.max(IVec2::ZERO);
+    let size = (max - min).max(IVec2::ZERO).as_uvec2();
+    let mut glyphs = RgbaImage::new(size.x, size.y);
+    for (corner, rect) in &placed {
+        for y in 0..rect.height() {
+            for x in 0..rect.width() {
+                let (sx, sy) = (rect.min.x + x, rect.min.y + y);
+                let target = *corner - min + UVec2::new(x, y).as_ivec2();
+                if sx >= atlas.width() || sy >= atlas.height() || target.x < 0 || target.y < 0 || target.x >= size.x as i32 || target.y >= size.y as i32 {
+                    continue;
+                }
+                let source = atlas.get_pixel(sx, sy);
+                if source[3] > 0 {
+                    glyphs.put_pixel(target.x as u32, target.y as u32, *source);
+                }
+            }
+        }
+    }
+
+    let color = |color: Color| -> [u8; 4] {
+        let mut linear = color.to_linear();
+        // the same levels the post-process palette quantizes to
+        if let Some(levels) = levels {
+            linear = quantize_linear(linear, levels);
+        }
+        Srgba::from(linear).to_u8_array()
+    };
+    let covered = |x: i32, y: i32| x >= 0 && y >= 0 && x < size.x as i32 && y < size.y as i32 && glyphs.get_pixel(x as u32, y as u32)[3] > 0;
+
+    let mut output = RgbaImage::new(size.x, size.y);
+    if let Some(outline_color) = text.outline {
+        let outline_color = color(outline_color);
+        for y in 0..size.y as i32 {
+            for x in 0..size.x as i32 {
+                if (-1..=1).any(|dy| (-1..=1).any(|dx| covered(x + dx, y + dy))) {
+                    output.put_pixel(x as u32, y as u32, image::Rgba(outline_color));
+                }
+            }
+        }
+    }
+    let tint = color(text.color);
+    for (x, y, pixel) in glyphs.enumerate_pixels() {
+        if pixel[3] > 0 {
+            let channel = |i: usize| (pixel[i] as u32 * tint[i] as u32 / 255) as u8;
+            output.put_pixel(x, y, image::Rgba([channel(0), channel(1), channel(2), channel(3)]));
+        }
+    }
+    // the shadow is cast by the outlined text, behind it
+    if let Some((offset, shadow_color)) = text.shadow {
+        let shadow_color = image::Rgba(color(shadow_color));
+        let body = output.clone();
+        for (x, y, pixel) in output.enumerate_pixels_mut() {
+            let (sx, sy) = (x as i32 - offset.x, y as i32 - offset.y);
+            let casts = sx >= 0 && sy >= 0 && sx < size.x as i32 && sy < size.y as i32 && body.get_pixel(sx as u32, sy as u32)[3] > 0;
+            if casts && pixel[3] == 0 {
+                *pixel = shadow_color;
+            }
+        }
+    }
+    output
+}
+
+/// Splits text into lines at newlines, and at spaces to stay within `max_width`, with each line's width.
+fn layout_lines(text: &str, font: &PixelFont, max_width: Option<u32>) -> Vec<(String, i32)> {
+    let width_of = |line: &str| {
+        let mut width = 0;
+        let mut previous = None;
+        for c in line.chars() {
+            let Some(glyph) = font.glyphs.get(&c) else {
+                continue;
+            };
+            if let Some(previous) = previous {
+                width += font.kerning.get(&(previous, c)).copied().unwrap_or(0);
+            }
+            width += glyph.advance;
+            previous = Some(c);
+        }
+        width
+    };
+
+    let mut lines = Vec::new();
+    for paragraph in text.split('\n') {
+        let Some(max_width) = max_width else {
+            lines.push((paragraph.to_string(), width_of(paragraph)));
+            continue;
+        };
+        let mut line = String::new();
+        for word in paragraph.split(' ') {
+            let candidate = if line.is_empty() { word.to_string() } else { format!("{line} {word}") };
+            if line.is_empty() || width_of(&candidate) <= max_width as i32 {
+                line = candidate;
+            } else {
+                lines.push((line.clone(), width_of(&line)));
+                line = word.to_string();
+            }
+        }
+        lines.push((line.clone(), width_of(&line)));
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A font whose glyphs are all the same white square from a 2x2 atlas.
+    fn square_font(chars: &str, kerning: &[((char, char), i32)]) -> (PixelFont, RgbaImage) {
+        let glyph = PixelGlyph {
+            rect: URect::new(0, 0, 2, 2),
+            offset: IVec2::ZERO,
+            advance: 4,
+        };
+        let font = PixelFont {
+            image: Handle::default(),
+            line_height: 3,
+            glyphs: chars.chars().map(|c| (c, glyph)).collect(),
+            kerning: kerning.iter().copied().collect(),
+        };
+        (font, RgbaImage::from_pixel(2, 2, image::Rgba([255; 4])))
+    }
+
+    #[test]
+    fn bmfont_fields_keep_quoted_spaces() {
+        let (tag, fields) = bmfont_fields(r#"page id=0 file="a b.png""#);
+        assert_eq!(tag, "page");
+        assert_eq!(fields, [("id", "0"), ("file", "a b.png")]);
+
+        let (tag, fields) = bmfont_fields(r#"info face="Tiny Font"  size=8 bold=0"#);
+        assert_eq!(tag, "info");
+        assert_eq!(fields, [("face", "Tiny Font"), ("size", "8"), ("bold", "0")]);
+    }
+
+    #[test]
+    fn line_widths_include_kerning() {
+        let (font, _) = square_font("ab ", &[(('a', 'b'), -1)]);
+        assert_eq!(layout_lines("ab", &font, None), [("ab".to_string(), 7)]);
+        assert_eq!(layout_lines("ba", &font, None), [("ba".to_string(), 8)]);
+        assert_eq!(layout_lines("a\nb", &font, None), [("a".to_string(), 4), ("b".to_string(), 4)]);
+    }
+
+    #[test]
+    fn lines_wrap_at_spaces() {
+        let (font, _) = square_font("ab ", &[(('a', 'b'), -1)]);
+        assert_eq!(
+            layout_lines("ab ab ab", &font, Some(18)),
+            [("ab ab".to_string(), 18), ("ab".to_string(), 7)],
+        );
+        // a word wider than the limit still gets a line of its own
+        assert_eq!(
+            layout_lines("a abababab", &font, Some(10)),
+            [("a".to_string(), 4), ("abababab".to_string(), 28)],
+        );
+    }
+
+    #[test]
+    fn outline_and_shadow_fit_with_negative_shadow_offset() {
+        let (font, atlas) = square_font("a", &[]);
+        let text = PixelText {
+            text: "a".into(),
+            color: Color::WHITE,
+            outline: Some(Color::BLACK),
+            shadow: Some((IVec2::new(-2, -1), Color::srgb(1., 0., 0.))),
+            ..default()
+        };
+        let image = render_text(&text, &font, &atlas, None);
+        // the 4x3 texel line, one texel of outline around it, and the shadow reaching 2 left and 1 up
+        assert_eq!(image.dimensions(), (8, 6));
+
+        let count = |color: [u8; 4]| image.pixels().filter(|pixel| pixel.0 == color).count();
+        assert_eq!(count([255, 255, 255, 255]), 4);
+        assert_eq!(count([0, 0, 0, 255]), 12, "the outline ring is cut off");
+        assert_eq!(count([255, 0, 0, 255]), 10, "the shadow is cut off");
+        assert_eq!(image.get_pixel(0, 0).0, [255, 0, 0, 255]);
+        assert_eq!(image.get_pixel(3, 2).0, [255, 255, 255, 255]);
+    }
+
+    #[test]
+    fn palette_quantizes_linear_colour() {
+        let (font, atlas) = square_font("a", &[]);
+        // sRGB 0.6 is about 0.32 linear, so a 1-bit palette rounds it down to black
+        let text = |value: f32| PixelText {
+            text: "a".into(),
+            color: Color::srgb(value, value, value),
+            ..default()
+        };
+        assert_eq!(render_text(&text(0.6), &font, &atlas, Some(1.)).get_pixel(0, 0).0, [0, 0, 0, 255]);
+        assert_eq!(render_text(&text(0.8), &font, &atlas, Some(1.)).get_pixel(0, 0).0, [255, 255, 255, 255]);
+    }
+
+    #[test]
+    fn glyphs_with_negative_offsets_are_not_cropped() {
+        let (_, fields) = bmfont_fields("char id=106 x=0 y=0 width=2 height=2 xoffset=-1 yoffset=-1 xadvance=2");
+        let int = |key: &str| fields.iter().find(|(k, _)| *k == key).unwrap().1.parse::<i32>().unwrap();
+        let (mut font, atlas) = square_font("", &[]);
+        font.glyphs.insert('j', PixelGlyph {
+            rect: URect::new(int("x") as u32, int("y") as u32, int("width") as u32, int("height") as u32),
+            offset: IVec2::new(int("xoffset"), int("yoffset")),
+            advance: int("xadvance"),
+        });
+        let text = PixelText {
+            text: "j".into(),
+            color: Color::WHITE,
+            ..default()
+        };
+        let image = render_text(&text, &font, &atlas, None);
+        // the glyph reaches one texel left of and above the pen
+        assert_eq!(image.dimensions(), (3, 4));
+        assert_eq!(image.pixels().filter(|pixel| pixel.0 == [255; 4]).count(), 4);
+        assert_eq!(image.get_pixel(0, 0).0, [255; 4]);
+    }
+}
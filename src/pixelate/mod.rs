@@ -1,5 +1,29 @@
+pub mod billboard;
+pub mod cursor;
+pub mod debug;
+pub mod font;
+pub mod particles;
 pub mod pixel_cam;
 pub mod postprocessing;
+pub mod recorder;
+pub mod screenshot;
+pub mod shadows;
+pub mod stepped;
+pub mod style;
+pub mod toon;
+pub mod view;
 
+pub use billboard::*;
+pub use cursor::*;
+pub use debug::*;
+pub use font::*;
+pub use particles::*;
 pub use pixel_cam::*;
-pub use postprocessing::*;
\ No newline at end of file
+pub use postprocessing::*;
+pub use recorder::*;
+pub use screenshot::*;
+pub use shadows::*;
+pub use stepped::*;
+pub use style::*;
+pub use toon::*;
+pub use view::*;
\ No newline at end of file
@@ -0,0 +1,264 @@
+use bevy::{
+    pbr::NotShadowCaster,
+    prelude::*,
+    render::{mesh::Indices, view::{NoFrustumCulling, VisibilitySystems}},
+    transform::TransformSystem,
+};
+
+use crate::{postprocessing::quantize_linear, PixelCamera, PostProcessSettings, PIXEL_PERFECT_LAYERS, RES_WIDTH};
+
+/// CPU particle emitter for the pixel-perfect world.
+/// Particles are squares of whole canvas texels, or a small sprite, snapped to the texel grid and facing the [`PixelCamera`].
+/// They are depth-tested against the scene but stay out of the prepass, so they don't get outlines.
+/// Particles live in world space, so they stay behind when the emitter moves.
+#[derive(Component, Reflect, Clone, Debug)]
+#[reflect(Component, Default)]
+#[require(Transform, Visibility)]
+pub struct PixelParticles {
+    pub emitting: bool,
+    /// Particles spawned per second.
+    pub rate: f32,
+    /// Seconds each particle lives.
+    pub lifetime: f32,
+    /// Half extents of the box around the emitter that particles spawn in.
+    pub area: Vec3,
+    pub velocity: Vec3,
+    /// Random variation added to [`velocity`](Self::velocity), up to this much either way on each axis.
+    pub spread: Vec3,
+    pub gravity: Vec3,
+    /// Side of each particle in canvas texels. Ignored when there is a [`sprite`](Self::sprite).
+    pub size: u32,
+    /// Optional sprite drawn for each particle, one sprite pixel per canvas texel.
+    pub sprite: Option<Handle<Image>>,
+    /// Colours the particles pass through over their lifetime, picked without blending.
+    /// They are snapped to the pixel camera's palette when it has one.
+    pub colors: Vec<Color>,
+    pub max_particles: usize,
+    /// Particles to spawn at once on the next frame, for bursts like sparks. Reset after spawning.
+    pub burst: usize,
+}
+
+impl Default for PixelParticles {
+    fn default() -> Self {
+        Self {
+            emitting: true,
+            rate: 20.,
+            lifetime: 1.,
+            area: Vec3::ZERO,
+            velocity: Vec3::Y * 10.,
+            spread: Vec3::splat(5.),
+            gravity: Vec3::ZERO,
+            size: 1,
+            sprite: None,
+            colors: vec![Color::WHITE],
+            max_particles: 1000,
+            burst: 0,
+        }
+    }
+}
+
+impl PixelParticles {
+    fn color(&self, age: f32) -> Color {
+        if self.colors.is_empty() {
+            return Color::WHITE;
+        }
+        let t = (age / self.lifetime.max(f32::EPSILON)).clamp(0., 1.);
+        let index = ((t * self.colors.len() as f32) as usize).min(self.colors.len() - 1);
+        self.colors[index]
+    }
+}
+
+/// Live particles of a [`PixelParticles`] emitter and the child entity that draws them.
+#[derive(Component)]
+struct ParticleState {
+    mesh: Entity,
+    particles: Vec<Particle>,
+    accumulated: f32,
+    seed: u32,
+}
+
+impl ParticleState {
+    fn spawn(&mut self, emitter: &PixelParticles, origin: Vec3, count: usize) {
+        for _ in 0..count {
+            if self.particles.len() >= emitter.max_particles {
+                break;
+            }
+            let offset = self.random_vec3() * emitter.area;
+            let velocity = emitter.velocity + self.random_vec3() * emitter.spread;
+            self.particles.push(Particle {
+                position: origin + offset,
+                velocity,
+                age: 0.,
+            });
+        }
+    }
+
+    /// Random vector with each component in -1..1.
+    fn random_vec3(&mut self) -> Vec3 {
+        Vec3::new(self.random(), self.random(), self.random()) * 2. - 1.
+    }
+
+    /// Random number in 0..1 from a xorshift generator.
+    fn random(&mut self) -> f32 {
+        self.seed ^= self.seed << 13;
+        self.seed ^= self.seed >> 17;
+        self.seed ^= self.seed << 5;
+        (self.seed >> 8) as f32 / (1 << 24) as f32
+    }
+}
+
+#[derive(Clone, Debug)]
+struct Particle {
+    position: Vec3,
+    velocity: Vec3,
+    age: f32,
+}
+
+pub struct PixelParticlesPlugin;
+
+impl Plugin for PixelParticlesPlugin {
+    fn build(&self, app: &mut App) {
+        app
+        .register_type::<PixelParticles>()
+        .add_systems(Update, (spawn_particle_meshes, simulate_particles))
+        .add_systems(PostUpdate, build_particle_meshes
+            .after(TransformSystem::TransformPropagate)
+            .before(VisibilitySystems::VisibilityPropagate));
+    }
+}
+
+fn spawn_particle_meshes(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    emitters: Query<(Entity, &PixelParticles), Without<ParticleState>>,
+){
+    for (entity, emitter) in &emitters {
+        let mesh = commands.spawn((
+            // an empty placeholder until there are particles to draw
+            Mesh3d(meshes.add(Rectangle::new(0., 0.))),
+            MeshMaterial3d(materials.add(StandardMaterial {
+                base_color_texture: emitter.sprite.clone(),
+                // blended meshes are depth-tested but skip the prepass
+                alpha_mode: AlphaMode::Blend,
+                unlit: true,
+                ..default()
+            })),
+            Visibility::Hidden,
+            NoFrustumCulling,
+            NotShadowCaster,
+            PIXEL_PERFECT_LAYERS,
+            ChildOf(entity),
+        )).id();
+        commands.entity(entity).insert(ParticleState {
+            mesh,
+            particles: Vec::new(),
+            accumulated: 0.,
+            // different emitters get different patterns
+            seed: entity.index().wrapping_mul(0x9e37_79b9) | 1,
+        });
+    }
+}
+
+fn simulate_particles(
+    time: Res<Time>,
+    mut emitters: Query<(&mut PixelParticles, &mut ParticleState, &GlobalTransform)>,
+){
+    let delta = time.delta_secs();
+    for (mut emitter, mut state, transform) in &mut emitters {
+        let (lifetime, gravity) = (emitter.lifetime, emitter.gravity);
+        state.particles.retain_mut(|particle| {
+            particle.age += delta;
+            particle.velocity += gravity * delta;
+            particle.position += particle.velocity * delta;
+            particle.age < lifetime
+        });
+
+        let mut count = 0;
+        if emitter.emitting {
+            state.accumulated += emitter.rate * delta;
+            count = state.accumulated.floor() as usize;
+            state.accumulated = state.accumulated.fract();
+        }
+        if emitter.burst > 0 {
+            count += std::mem::take(&mut emitter.burst);
+        }
+        state.spawn(&emitter, transform.translation(), count);
+    }
+}
+
+/// Rebuilds each emitter's mesh from its particles, facing the camera and snapped to the canvas texels.
+fn build_particle_meshes(
+    cam: Single<(&Transform, &Projection, &PostProcessSettings), With<PixelCamera>>,
+    emitters: Query<(&PixelParticles, &ParticleState, &GlobalTransform)>,
+    mut particle_meshes: Query<(&Mesh3d, &mut Visibility)>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    images: Res<Assets<Image>>,
+){
+    let (cam_t, projection, settings) = cam.into_inner();
+    let Projection::Orthographic(ortho) = projection else {
+        return;
+    };
+    let texel = ortho.area.width() / RES_WIDTH as f32;
+    if texel <= 0. {
+        return;
+    }
+    let (right, up, back) = (cam_t.right(), cam_t.up(), cam_t.back());
+    let levels = settings.palette_levels();
+
+    for (emitter, state, transform) in &emitters {
+        let Ok((mesh3d, mut visibility)) = particle_meshes.get_mut(state.mesh) else {
+            continue;
+        };
+        let empty = state.particles.is_empty();
+        visibility.set_if_neq(if empty { Visibility::Hidden } else { Visibility::Inherited });
+        if empty {
+            continue;
+        }
+        let Some(mesh) = meshes.get_mut(&mesh3d.0) else {
+            continue;
+        };
+
+        let size = match emitter.sprite.as_ref().and_then(|sprite| images.get(sprite)) {
+            Some(image) => image.size(),
+            None => UVec2::splat(emitter.size.max(1)),
+        };
+        let offset = (size / 2).as_vec2();
+        let extent = size.as_vec2() * texel;
+        // the mesh is a child of the emitter, but the particles are in world space
+        let to_local = transform.affine().inverse();
+
+        let count = state.particles.len();
+        let mut positions = Vec::with_capacity(count * 4);
+        let mut colors = Vec::with_capacity(count * 4);
+        let mut uvs = Vec::with_capacity(count * 4);
+        let mut indices = Vec::with_capacity(count * 6);
+        for particle in &state.particles {
+            let snapped = (Vec2::new(particle.position.dot(*right), particle.position.dot(*up)) / texel).round();
+            let corner = (snapped - offset) * texel;
+            let origin = right * corner.x + up * corner.y + back * particle.position.dot(*back);
+
+            let mut color = emitter.color(particle.age).to_linear();
+            if let Some(levels) = levels {
+                color = quantize_linear(color, levels);
+            }
+            let color = color.to_f32_array();
+
+            let base = positions.len() as u32;
+            for (x, y) in [(0., 0.), (1., 0.), (1., 1.), (0., 1.)] {
+                let position = origin + right * extent.x * x + up * extent.y * y;
+                positions.push(to_local.transform_point3(position).to_array());
+                colors.push(color);
+                uvs.push([x, 1. - y]);
+            }
+            indices.extend_from_slice(&[base, base + 1, base + 2, base, base + 2, base + 3]);
+        }
+
+        let normal = to_local.transform_vector3(*back).normalize_or_zero().to_array();
+        mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, positions);
+        mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, vec![normal; count * 4]);
+        mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, uvs);
+        mesh.insert_attribute(Mesh::ATTRIBUTE_COLOR, colors);
+        mesh.insert_indices(Indices::U32(indices));
+    }
+}
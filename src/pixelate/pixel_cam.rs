@@ -1,7 +1,7 @@
 
-use bevy::{core_pipeline::{bloom::Bloom, prepass::{DepthPrepass, NormalPrepass}, tonemapping::{DebandDither, Tonemapping}}, math::FloatOrd, prelude::*, render::{camera::{ImageRenderTarget, RenderTarget}, render_resource::{Extent3d, TextureDescriptor, TextureDimension, TextureFormat, TextureUsages}, view::RenderLayers}, sprite::AlphaMode2d, window::WindowResized};
+use bevy::{core_pipeline::{bloom::Bloom, tonemapping::{DebandDither, Tonemapping}}, ecs::{entity::MapEntities, reflect::ReflectMapEntities}, math::FloatOrd, prelude::*, render::{camera::{CameraOutputMode, CameraUpdateSystem, ImageRenderTarget, RenderTarget}, mesh::MeshVertexBufferLayoutRef, render_resource::{AsBindGroup, BlendState, Extent3d, RenderPipelineDescriptor, ShaderRef, SpecializedMeshPipelineError, TextureDescriptor, TextureDimension, TextureFormat, TextureUsages}, view::RenderLayers}, sprite::{AlphaMode2d, Material2d, Material2dKey, Material2dPlugin}, transform::TransformSystem, window::WindowResized};
 
-use crate::PostProcessSettings;
+use crate::{input::init_pixel_input, PixelInput, PixelInputMap, PixelShadows, PostProcessSettings};
 
 /// In-game resolution width.
 pub const RES_WIDTH: u32 = 640;
@@ -16,26 +16,114 @@ pub const PIXEL_PERFECT_LAYERS: RenderLayers = RenderLayers::layer(0);
 /// Render layers for high-resolution rendering.
 pub const HIGH_RES_LAYERS: RenderLayers = RenderLayers::layer(1);
 
+/// Render layers for 2D sprites drawn into the canvas on top of the pixel-perfect world,
+/// by the [`PixelUiCamera`].
+pub const PIXEL_UI_LAYERS: RenderLayers = RenderLayers::layer(2);
+
 /// Low-resolution texture that contains the pixel-perfect world.
 /// Canvas itself is rendered to the high-resolution world.
 #[derive(Component)]
-struct Canvas;
+pub(crate) struct Canvas;
 
+/// Quad showing what the [`PixelUiCamera`] drew, over the [`Canvas`] but without its scroll offset.
 #[derive(Component)]
+struct PixelUiCanvas;
+
+/// Draws the [`PixelUiCamera`]'s texture, whose colour is premultiplied by alpha.
+/// `ColorMaterial` blends with straight alpha, which darkens semi-transparent HUD pixels.
+#[derive(Asset, AsBindGroup, TypePath, Clone)]
+struct PixelUiMaterial {
+    #[texture(0)]
+    #[sampler(1)]
+    image: Handle<Image>,
+}
+
+impl Material2d for PixelUiMaterial {
+    fn fragment_shader() -> ShaderRef {
+        "shaders/pixel_ui.wgsl".into()
+    }
+
+    fn alpha_mode(&self) -> AlphaMode2d {
+        AlphaMode2d::Blend
+    }
+
+    fn specialize(
+        descriptor: &mut RenderPipelineDescriptor,
+        _layout: &MeshVertexBufferLayoutRef,
+        _key: Material2dKey<Self>,
+    ) -> Result<(), SpecializedMeshPipelineError> {
+        if let Some(target) = descriptor.fragment.as_mut().and_then(|fragment| fragment.targets[0].as_mut()) {
+            target.blend = Some(BlendState::PREMULTIPLIED_ALPHA_BLENDING);
+        }
+        Ok(())
+    }
+}
+
+#[derive(Component, Reflect, Default)]
+#[reflect(Component, Default)]
 pub struct CameraTarget;
 
 /// Camera that renders the pixel-perfect world to the [`Canvas`].
-#[derive(Component)]
+#[derive(Component, Reflect, Default)]
+#[reflect(Component, Default)]
 pub struct PixelCamera{
-    pub subpixel_position: Vec2
+    pub subpixel_position: Vec2,
+    pub scroll: ScrollMode,
+}
+
+/// How the canvas follows the part of the camera's movement smaller than a texel.
+#[derive(Reflect, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ScrollMode {
+    /// The view moves in whole texels, like classic hardware.
+    Locked,
+    /// The canvas is shifted back by the remainder, so scrolling is smooth at window resolution.
+    #[default]
+    Smooth,
+    /// Smooth while the camera moves, easing onto the texel grid when it stops.
+    SmoothWhileMoving,
+}
+
+/// How fast [`ScrollMode::SmoothWhileMoving`] eases onto the texel grid, per second.
+const SETTLE_RATE: f32 = 12.;
+
+/// Movement below this fraction of a texel per frame counts as standing still.
+const STILL_EPSILON: f32 = 1e-3;
+
+/// The canvas offset in texels [`place_camera`] applied last frame, and the position it was for.
+#[derive(Default)]
+struct ScrollState {
+    previous: Option<Vec2>,
+    offset: Vec2,
+}
+
+/// Keeps an entity on [`HIGH_RES_LAYERS`] over a point in the pixel-perfect world, moving with
+/// the canvas' scroll offset so it stays on the same texels.
+#[derive(Component, Reflect, MapEntities, Clone, Copy, Debug)]
+#[reflect(Component, MapEntities)]
+pub enum HighResAnchor {
+    Position(Vec3),
+    /// Follows the entity's [`GlobalTransform`] as of the last frame.
+    Entity(#[entities] Entity),
 }
 
 /// Camera that renders the [`Canvas`] (and other graphics on [`HIGH_RES_LAYERS`]) to the screen.
 #[derive(Component)]
 struct OuterCamera;
 
+/// 2D camera that draws Bevy UI and sprites on [`PIXEL_UI_LAYERS`] at the canvas' resolution,
+/// so a HUD lines up with the game's texels and gets no outlines.
+/// Spawned when [`PixelCamSettings::pixel_ui`] is on; give UI root nodes a `UiTargetCamera` with it.
+///
+/// With the outer camera it draws to its own texture, shown over the canvas without the
+/// sub-texel scroll offset so the HUD stays still while the world scrolls. Screenshots and
+/// recordings blend it over the [`PixelCanvas`] the same way. Without the outer camera nothing scrolls, and it draws straight into the canvas after the post-process.
+#[derive(Component, Reflect, Default)]
+#[reflect(Component, Default)]
+pub struct PixelUiCamera;
+
 /// Camera that renders the [`Canvas`] (and other graphics on [`HIGH_RES_LAYERS`]) to the screen.
-#[derive(Resource)]
+#[derive(Resource, Reflect)]
+#[reflect(Resource)]
 pub struct WindowSize{
     pub width: f32,
     pub height: f32,
@@ -43,43 +131,68 @@ pub struct WindowSize{
     pub zoom: f32,
 }
 
-#[derive(Resource)]
-pub struct ShowSettings{
-    pub value: i32
+/// Rendering options for the [`PixelCamera`].
+/// Insert it before adding [`PixelCamPlugin`] to change the defaults.
+#[derive(Resource, Reflect)]
+#[reflect(Resource, Default)]
+pub struct PixelCamSettings{
+    /// Renders the pixel-perfect world in HDR with bloom.
+    /// Turn it off for low-end machines; the post-process works on both.
+    pub hdr: bool,
+    /// Spawns the camera that draws the canvas to the window.
+    /// Turn it off to render without a window, e.g. for [`PixelScreenshot`](crate::PixelScreenshot)s in CI.
+    pub outer_camera: bool,
+    /// Spawns the [`PixelUiCamera`] for a low-resolution HUD inside the canvas.
+    pub pixel_ui: bool,
 }
 
+impl Default for PixelCamSettings {
+    fn default() -> Self {
+        Self { hdr: true, outer_camera: true, pixel_ui: false }
+    }
+}
+
+/// The low-resolution image the [`PixelCamera`] renders to, at [`RES_WIDTH`] x [`RES_HEIGHT`].
+#[derive(Resource, Reflect, Clone)]
+#[reflect(Resource)]
+pub struct PixelCanvas(pub Handle<Image>);
+
+/// The transparent texture the [`PixelUiCamera`] draws to when the canvas scrolls under the HUD.
+#[derive(Resource, Clone)]
+pub(crate) struct PixelUiImage(pub Handle<Image>);
+
 pub struct PixelCamPlugin;
 
 impl Plugin for PixelCamPlugin {
     fn build(&self, app: &mut App) {
+        init_pixel_input(app);
         app
+        .register_type::<PixelCamera>()
+        .register_type::<PixelUiCamera>()
+        .register_type::<ScrollMode>()
+        .register_type::<HighResAnchor>()
+        .register_type::<CameraTarget>()
+        .register_type::<CameraPosition>()
+        .register_type::<WindowSize>()
+        .register_type::<PixelCamSettings>()
+        .register_type::<PixelCanvas>()
         .insert_resource(WindowSize{
             width: 0.,
             height: 0.,
             texel_size: 0.,
             zoom: 5.
         })
-        .insert_resource(ShowSettings{value: 0})
+        .init_resource::<PixelCamSettings>()
+        .add_plugins(Material2dPlugin::<PixelUiMaterial>::default())
         .add_systems(Startup, setup_camera)
-        .add_systems(Update, fit_canvas)
-        .add_systems(Update, (update_settings, camera_follow, place_camera));
-    }
-}
-
-fn update_settings(
-    mut settings: Query<&mut PostProcessSettings>,
-    keycode: Res<ButtonInput<KeyCode>>,
-    mut show_depth: ResMut<ShowSettings>,
-) {
-    if keycode.just_pressed(KeyCode::Space) {
-        for mut setting in settings.iter_mut() {
-            show_depth.value = (show_depth.value + 1) % 3;
-            setting.show_depth = (show_depth.value == 1) as u32;
-            setting.show_normals = (show_depth.value == 2) as u32;
-        }
+        .add_systems(Update, (fit_canvas, apply_cam_settings))
+        .add_systems(Update, (camera_follow, place_camera))
+        .add_systems(Update, camera_controls.before(place_camera))
+        .add_systems(PostUpdate, anchor_high_res.after(CameraUpdateSystem).before(TransformSystem::TransformPropagate));
     }
 }
 
+#[allow(clippy::type_complexity)]
 fn camera_follow(
     mut cam: Single<(&mut PixelCamera,&Camera), (With<PixelCamera>, Without<CameraPosition>)>,
     cam_t: Single<&GlobalTransform, With<CameraPosition>>,
@@ -94,50 +207,71 @@ fn camera_follow(
     }
 }
 
-fn camera_movement(
+/// Zooms and orbits the pixel camera from [`PixelInput`].
+#[allow(clippy::type_complexity)]
+fn camera_controls(
     mut window: ResMut<WindowSize>,
-    mut cam: Single<(&mut PixelCamera, &mut Projection), With<PixelCamera>>,
-    key_input: ResMut<ButtonInput<KeyCode>>,
+    input: Res<PixelInput>,
+    map: Res<PixelInputMap>,
+    mut cam: Single<(&mut PixelCamera, &mut Transform, &mut Projection), (With<PixelCamera>, Without<CameraPosition>)>,
+    mut cam_t: Single<&mut Transform, (With<CameraPosition>, Without<CameraTarget>)>,
+    target_q: Option<Single<&Transform, (With<CameraTarget>, Without<PixelCamera>, Without<CameraPosition>)>>,
     time: Res<Time>
 ){
-    // let mut pixelcam = cam.single_mut();
-    let mut dir = Vec2::splat(0.0);
-    if key_input.pressed(KeyCode::KeyA) {
-        dir.x -= 1.0;
-    }
-    if key_input.pressed(KeyCode::KeyD) {
-        dir.x += 1.0;
-    }
-    if key_input.pressed(KeyCode::KeyW) {
-        dir.y += 1.0;
+    if input.zoom != 0. {
+        window.zoom += input.zoom * time.delta_secs() * map.zoom_speed;
+        window.zoom = window.zoom.clamp(1., 10.);
+
+        if let Projection::Orthographic(ortho) = cam.2.as_mut() {
+            ortho.scale = 1. / window.zoom;
+        }
     }
-    if key_input.pressed(KeyCode::KeyS) {
-        dir.y -= 1.0;
+
+    if input.rotate != 0. {
+        // orbit around the target, keeping it where it is on the screen
+        let pivot = target_q.map_or(Vec3::ZERO, |target| target.translation);
+        let rotation = Quat::from_rotation_y(-input.rotate * time.delta_secs() * map.rotate_speed);
+        let pos = cam.0.subpixel_position;
+        let center = cam.1.right() * pos.x + cam.1.up() * pos.y;
+        let center = pivot + rotation * (center - pivot);
+
+        cam.1.rotate(rotation);
+        cam_t.rotate(rotation);
+        cam.0.subpixel_position = Vec2::new(center.dot(*cam.1.right()), center.dot(*cam.1.up()));
     }
-    let mut zoom = 0.;
-    if key_input.pressed(KeyCode::KeyQ) {
-        zoom -= 1.0;
+}
+
+/// Keeps the pixel camera's HDR and bloom in sync with [`PixelCamSettings`].
+fn apply_cam_settings(
+    mut commands: Commands,
+    settings: Res<PixelCamSettings>,
+    mut cam: Single<(Entity, &mut Camera), With<PixelCamera>>,
+){
+    if !settings.is_changed() || cam.1.hdr == settings.hdr {
+        return;
     }
-    if key_input.pressed(KeyCode::KeyE) {
-        zoom += 1.0;
+    cam.1.hdr = settings.hdr;
+    if settings.hdr {
+        commands.entity(cam.0).insert(pixel_bloom());
+    } else {
+        commands.entity(cam.0).remove::<Bloom>();
     }
-    if zoom != 0. {
-        window.zoom += zoom * time.delta_secs() * 10.;
-        window.zoom = window.zoom.clamp(1., 10.);
-        
-        *cam.1 = Projection::Orthographic(OrthographicProjection { scale: 1./window.zoom,
-                far: 10000.,
-                near: -1000., ..OrthographicProjection::default_3d() });
+}
 
+fn pixel_bloom() -> Bloom {
+    Bloom {
+        low_frequency_boost: 0.25,
+        ..default()
     }
-    // cam.subpixel_position += dir.normalize_or_zero() * time.delta_secs() * 10.;
 }
 
 fn setup_camera(
     mut commands: Commands, 
-    window: Res<WindowSize>,
+    mut window: ResMut<WindowSize>,
+    settings: Res<PixelCamSettings>,
     mut images: ResMut<Assets<Image>>,
     mut materials: ResMut<Assets<ColorMaterial>>,
+    mut ui_materials: ResMut<Assets<PixelUiMaterial>>,
     mut meshes: ResMut<Assets<Mesh>>,
 ) {
     let canvas_size = Extent3d {
@@ -166,14 +300,13 @@ fn setup_camera(
     // fill image.data with zeroes
     canvas.resize(canvas_size);
 
+    // the HUD gets a transparent texture of its own when the canvas scrolls under it
+    let ui_image = (settings.pixel_ui && settings.outer_camera).then(|| images.add(canvas.clone()));
     let image_handle = images.add(canvas);
+    commands.insert_resource(PixelCanvas(image_handle.clone()));
 
     // this camera renders whatever is on `PIXEL_PERFECT_LAYERS` to the canvas
-    let mut bloom = Bloom::default();
-
-    bloom.low_frequency_boost = 0.25;
-        
-    commands.spawn((
+    let mut camera = commands.spawn((
         Projection::from(OrthographicProjection{
             scale: 1./window.zoom,
             far: 10000.,
@@ -183,7 +316,7 @@ fn setup_camera(
         Camera {
             // render before the "main pass" camera
             // order: 1,
-            hdr: true,
+            hdr: settings.hdr,
             clear_color: ClearColorConfig::Custom(Color::BLACK),
             order: -1,
             target: RenderTarget::Image(ImageRenderTarget{handle: image_handle.clone(), scale_factor: FloatOrd(1.0)}),
@@ -196,21 +329,50 @@ fn setup_camera(
             ..default()
         },
         Transform::from_translation(Vec3::new(1., 1., -1.)).looking_at(Vec3::ZERO, Vec3::Y),
-        PixelCamera{subpixel_position: Vec2::new(0.,0.)},
-        PostProcessSettings {
-            ..default()
-        },
+        PixelCamera{subpixel_position: Vec2::new(0.,0.), scroll: ScrollMode::default()},
+        PostProcessSettings::default(),
         Tonemapping::TonyMcMapface, 
-        bloom,         
         DebandDither::Enabled,
-        Msaa::Off,
-        DepthPrepass,
-        NormalPrepass,
         PIXEL_PERFECT_LAYERS,
     ));
+    // bloom needs an HDR target
+    if settings.hdr {
+        camera.insert(pixel_bloom());
+    }
 
     commands.spawn((CameraPosition, Transform::from_translation(Vec3::new(1., 1., -1.)).looking_at(Vec3::ZERO, Vec3::Y)));
 
+    // this camera draws the HUD at the canvas' resolution, one UI pixel per texel
+    if settings.pixel_ui {
+        let (target, output_mode) = match &ui_image {
+            Some(ui_image) => (ui_image.clone(), CameraOutputMode::default()),
+            // its own texture starts transparent and is blended onto the finished canvas
+            None => (image_handle.clone(), CameraOutputMode::Write {
+                blend_state: Some(BlendState::PREMULTIPLIED_ALPHA_BLENDING),
+                clear_color: ClearColorConfig::None,
+            }),
+        };
+        commands.spawn((
+            Camera2d,
+            Camera {
+                order: 0,
+                clear_color: ClearColorConfig::Custom(Color::NONE),
+                output_mode,
+                target: RenderTarget::Image(ImageRenderTarget{handle: target, scale_factor: FloatOrd(1.0)}),
+                ..default()
+            },
+            PixelUiCamera,
+            Msaa::Off,
+            PIXEL_UI_LAYERS,
+        ));
+    }
+
+    if !settings.outer_camera {
+        // nothing fits the canvas to a window, so keep it at its native size
+        window.texel_size = 1.;
+        return;
+    }
+
     // spawn the canvas
     commands.spawn((
         Mesh2d(meshes.add(Rectangle::new(RES_WIDTH as f32, RES_HEIGHT as f32))),
@@ -225,13 +387,30 @@ fn setup_camera(
         HIGH_RES_LAYERS,
     ));
 
+    if let Some(ui_image) = ui_image {
+        commands.insert_resource(PixelUiImage(ui_image.clone()));
+        commands.spawn((
+            Mesh2d(meshes.add(Rectangle::new(RES_WIDTH as f32, RES_HEIGHT as f32))),
+            MeshMaterial2d(ui_materials.add(PixelUiMaterial{image: ui_image})),
+            Transform::from_xyz(0., 0., 1.),
+            PixelUiCanvas,
+            Msaa::Off,
+            HIGH_RES_LAYERS,
+        ));
+    }
+
     // the "outer" camera renders whatever is on `HIGH_RES_LAYERS` to the screen.
     // here, the canvas and one of the sample sprites will be rendered by this camera
     commands.spawn((
         Projection::from(OrthographicProjection{
             ..OrthographicProjection::default_2d()
         }),
-        Camera2d::default(),
+        Camera2d,
+        // after the pixel UI has been drawn into the canvas
+        Camera {
+            order: 1,
+            ..default()
+        },
         OuterCamera,
         Msaa::Off,
         HIGH_RES_LAYERS
@@ -245,42 +424,312 @@ fn fit_canvas(
     mut projections: Single<&mut Projection, With<OuterCamera>>,
 ) {
     for event in resize_events.read() {
-        let h_scale = event.width / (RES_WIDTH as f32 * 0.8).round();
-        let v_scale = event.height / (RES_HEIGHT as f32 * 0.8).round();
-        **projections = Projection::Orthographic(OrthographicProjection { scale: 1. / h_scale.min(v_scale).round(), ..OrthographicProjection::default_2d() });
-        
+        let scale = compute_canvas_scale(event.width, event.height);
+        **projections = Projection::Orthographic(OrthographicProjection { scale: 1. / scale, ..OrthographicProjection::default_2d() });
 
         window.width = event.width;
         window.height = event.height;
-        window.texel_size = h_scale.min(v_scale).round();
+        window.texel_size = scale;
     }
 }
 
-#[derive(Component)]
+/// Window pixels per canvas texel for a window of the given size.
+///
+/// The canvas is scaled to the whole multiple nearest to filling 80% of the window, so texels
+/// stay square and equally sized. Rounding up crops the canvas at the window's edges (a
+/// 1280x720 window shows the middle of a 3x, 1920x1080 canvas). The result is always a whole
+/// number and at least 1, even for a minimized window.
+pub fn compute_canvas_scale(window_width: f32, window_height: f32) -> f32 {
+    let h_scale = window_width / (RES_WIDTH as f32 * 0.8).round();
+    let v_scale = window_height / (RES_HEIGHT as f32 * 0.8).round();
+    h_scale.min(v_scale).round().max(1.)
+}
+
+/// Splits a camera position into the part on the texel grid and the remainder.
+///
+/// Returns the position rounded to the nearest multiple of `texel`, which the pixel camera
+/// moves to so the scene doesn't swim, and the offset from `position` to it, which the canvas
+/// is shifted back by to keep the motion smooth. Each component of the offset is at most
+/// half a texel. A `texel` of 0 (before the window size is known) leaves the position as is.
+pub fn snap_to_texel_grid(position: Vec2, texel: f32) -> (Vec2, Vec2) {
+    if texel == 0. {
+        return (position, Vec2::ZERO);
+    }
+    let snapped = (position / texel).round() * texel;
+    (snapped, snapped - position)
+}
+
+/// The pixel camera's position on the texel grid for a camera at `position`, and how far the
+/// canvas is shifted back to show the remainder, in canvas texels. The pixel camera's
+/// orthographic scale is `1 / zoom`, so one texel is `1 / zoom` world units.
+fn texel_grid_position(position: Vec2, zoom: f32) -> (Vec2, Vec2) {
+    let (snapped, offset) = snap_to_texel_grid(position, 1. / zoom);
+    (snapped, offset * zoom)
+}
+
+/// The canvas offset in texels that `mode` shows for the grid remainder `offset`, given whether
+/// the camera moved this frame and the offset shown last frame.
+fn scroll_offset(mode: ScrollMode, offset: Vec2, moving: bool, previous: Vec2, delta_secs: f32) -> Vec2 {
+    match mode {
+        ScrollMode::Locked => Vec2::ZERO,
+        ScrollMode::Smooth => offset,
+        ScrollMode::SmoothWhileMoving if moving => offset,
+        // the grid position is fixed once still, so only the leftover offset has to shrink
+        ScrollMode::SmoothWhileMoving => {
+            let eased = previous * (-SETTLE_RATE * delta_secs).exp();
+            if eased.length() < STILL_EPSILON { Vec2::ZERO } else { eased }
+        }
+    }
+}
+
+#[derive(Component, Reflect, Default)]
+#[reflect(Component, Default)]
 pub struct CameraPosition;
 
+#[allow(clippy::type_complexity)]
 fn place_camera(
     window: Res<WindowSize>,
-    mut cam: Single<(&PixelCamera, &mut Transform, &mut Projection), (With<PixelCamera>, Without<CameraPosition>)>,
-    mut canvas_q: Single<(&Canvas, &mut Transform), (With<Canvas>, Without<PixelCamera>, Without<CameraPosition>)>,
+    time: Res<Time>,
+    mut scroll: Local<ScrollState>,
+    mut cam: Single<(&PixelCamera, &mut Transform, Option<&PixelShadows>), (With<PixelCamera>, Without<CameraPosition>)>,
+    canvas_q: Option<Single<(&Canvas, &mut Transform), (With<Canvas>, Without<PixelCamera>, Without<CameraPosition>)>>,
     mut cam_t: Single<&mut Transform, With<CameraPosition>>,
 ){
     let pos = cam.0.subpixel_position;
-    let size = window.texel_size / window.zoom;
-    if size == 0. {
+    if window.zoom <= 0. {
         return;
     }
 
     let right = cam.1.right().mul_add(Vec3::ONE, Vec3::ZERO);
     let up = cam.1.up().mul_add(Vec3::ONE, Vec3::ZERO);
 
-    let norm = Vec2::new(
-        (pos.x / size).round() * size,
-        (pos.y / size).round() * size,
-    );
+    let (norm, offset) = texel_grid_position(pos, window.zoom);
+    let moving = scroll.previous.replace(pos).is_some_and(|previous| previous.distance(pos) * window.zoom > STILL_EPSILON);
+    let offset = scroll_offset(cam.0.scroll, offset, moving, scroll.offset, time.delta_secs());
+    scroll.offset = offset;
     let translate: Vec3 = right * norm.x + up * norm.y;
 
-    cam.1.translation = translate;
+    // the orthographic image doesn't change, but the shadow cascade starts at the camera
+    let pull_back = cam.2.map_or(0., |shadows| shadows.depth / 2.);
+    cam.1.translation = translate + cam.1.back() * pull_back;
     cam_t.translation = right * pos.x + up * pos.y;
-    canvas_q.1.translation = Vec3::new(norm.x - pos.x, norm.y - pos.y, 0.) * window.zoom;
-}
\ No newline at end of file
+    if let Some(mut canvas_q) = canvas_q {
+        canvas_q.1.translation = offset.extend(0.);
+    }
+}
+
+/// Moves [`HighResAnchor`]ed entities over their point on the canvas, including its scroll offset.
+#[allow(clippy::type_complexity)]
+fn anchor_high_res(
+    cam: Single<(&Camera, &Transform), With<PixelCamera>>,
+    canvas: Option<Single<&Transform, (With<Canvas>, Without<PixelCamera>)>>,
+    targets: Query<&GlobalTransform>,
+    mut anchored: Query<(&HighResAnchor, &mut Transform), (Without<PixelCamera>, Without<Canvas>)>,
+) {
+    let Some(canvas) = canvas else {
+        return;
+    };
+    // the pixel camera has no parent, so its transform is already global
+    let (camera, cam_t) = *cam;
+    let cam_t = GlobalTransform::from(*cam_t);
+    let half_canvas = Vec2::new(RES_WIDTH as f32, RES_HEIGHT as f32) / 2.;
+    for (anchor, mut transform) in &mut anchored {
+        let world = match anchor {
+            HighResAnchor::Position(position) => *position,
+            HighResAnchor::Entity(entity) => match targets.get(*entity) {
+                Ok(target) => target.translation(),
+                Err(_) => continue,
+            },
+        };
+        let Ok(viewport) = camera.world_to_viewport(&cam_t, world) else {
+            continue;
+        };
+        let position = Vec2::new(viewport.x - half_canvas.x, half_canvas.y - viewport.y) + canvas.translation.truncate();
+        transform.translation = position.extend(transform.translation.z);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Deterministic xorshift, so property failures reproduce. Use a different non-zero `seed`
+    /// for each input so they aren't correlated.
+    fn random_values(seed: u32, count: usize, min: f32, max: f32) -> impl Iterator<Item = f32> {
+        let mut state = seed;
+        (0..count).map(move |_| {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            min + (state as f32 / u32::MAX as f32) * (max - min)
+        })
+    }
+
+    #[test]
+    fn snapping_keeps_grid_positions() {
+        let (snapped, offset) = snap_to_texel_grid(Vec2::new(3., -6.), 1.5);
+        assert_eq!(snapped, Vec2::new(3., -6.));
+        assert_eq!(offset, Vec2::ZERO);
+    }
+
+    #[test]
+    fn snapping_rounds_to_nearest_texel() {
+        let (snapped, offset) = snap_to_texel_grid(Vec2::new(1.3, -0.9), 0.5);
+        assert_eq!(snapped, Vec2::new(1.5, -1.));
+        assert!(offset.abs_diff_eq(Vec2::new(0.2, -0.1), 1e-6));
+    }
+
+    #[test]
+    fn snapping_without_texel_size_is_a_no_op() {
+        let position = Vec2::new(0.37, 12.1);
+        assert_eq!(snap_to_texel_grid(position, 0.), (position, Vec2::ZERO));
+    }
+
+    #[test]
+    fn canvas_offset_stays_within_one_texel() {
+        let positions = random_values(0x2545_f491, 2000, -1e3, 1e3);
+        let texels = random_values(0x9e37_79b9, 2000, 0.01, 10.).collect::<Vec<_>>();
+        for (i, x) in positions.enumerate() {
+            let texel = texels[i];
+            let position = Vec2::new(x, -x * 0.37);
+            let (snapped, offset) = snap_to_texel_grid(position, texel);
+            let tolerance = texel * 1e-3 + position.abs().max_element() * f32::EPSILON * 2.;
+
+            assert!(offset.abs().max_element() <= texel / 2. + tolerance, "offset {offset} for texel {texel} at {position}");
+            assert!(((position + offset) - snapped).abs().max_element() <= tolerance);
+            let cells = snapped / texel;
+            let cell_tolerance = 1e-4 + cells.abs().max_element() * f32::EPSILON * 4.;
+            assert!((cells - cells.round()).abs().max_element() <= cell_tolerance, "{snapped} is off the grid of {texel}");
+        }
+    }
+
+    #[test]
+    fn camera_offset_is_within_half_a_canvas_texel() {
+        let positions = random_values(0x68e3_1da4, 2000, -1e3, 1e3);
+        let zooms = random_values(0xb529_7a4d, 2000, 0.1, 20.);
+        for (x, zoom) in positions.zip(zooms) {
+            let position = Vec2::new(x, x * 0.61);
+            let (snapped, offset) = texel_grid_position(position, zoom);
+            let tolerance = 1e-3 + position.abs().max_element() * zoom * f32::EPSILON * 4.;
+            assert!(offset.abs().max_element() <= 0.5 + tolerance, "offset {offset} texels at zoom {zoom}");
+            // shifting the canvas by the offset shows the camera where it really is
+            assert!((snapped - offset / zoom - position).abs().max_element() * zoom <= tolerance);
+        }
+    }
+
+    #[test]
+    fn locked_scroll_steps_one_texel_at_a_time() {
+        let zoom = 4.;
+        let mut previous = texel_grid_position(Vec2::ZERO, zoom).0;
+        // a slow pan, a fraction of a texel per frame
+        for frame in 1..400 {
+            let position = Vec2::new(frame as f32 * 0.3, frame as f32 * -0.45) / zoom;
+            let (snapped, offset) = texel_grid_position(position, zoom);
+            assert_eq!(scroll_offset(ScrollMode::Locked, offset, true, Vec2::ZERO, 1. / 60.), Vec2::ZERO);
+            let step = ((snapped - previous) * zoom).abs();
+            assert!(step.max_element() <= 1. + 1e-3, "jumped {step} texels on frame {frame}");
+            assert!((step - step.round()).abs().max_element() <= 1e-3, "moved off the grid on frame {frame}");
+            previous = snapped;
+        }
+    }
+
+    #[test]
+    fn smooth_while_moving_eases_to_the_grid() {
+        let offset = Vec2::new(0.4, -0.3);
+        assert_eq!(scroll_offset(ScrollMode::SmoothWhileMoving, offset, true, Vec2::ZERO, 1. / 60.), offset);
+        let mut shown = offset;
+        for _ in 0..3 {
+            let next = scroll_offset(ScrollMode::SmoothWhileMoving, offset, false, shown, 1. / 60.);
+            assert!(next.length() < shown.length() && next != Vec2::ZERO, "{next} should ease from {shown}");
+            shown = next;
+        }
+        for _ in 0..120 {
+            shown = scroll_offset(ScrollMode::SmoothWhileMoving, offset, false, shown, 1. / 60.);
+        }
+        assert_eq!(shown, Vec2::ZERO);
+    }
+
+    #[test]
+    fn canvas_scale_fits_common_resolutions() {
+        assert_eq!(compute_canvas_scale(1280., 720.), 3.);
+        assert_eq!(compute_canvas_scale(1920., 1080.), 4.);
+        assert_eq!(compute_canvas_scale(3840., 2160.), 8.);
+        // the narrower side decides
+        assert_eq!(compute_canvas_scale(3840., 720.), 3.);
+    }
+
+    #[test]
+    fn canvas_scale_is_at_least_one() {
+        assert_eq!(compute_canvas_scale(0., 0.), 1.);
+        assert_eq!(compute_canvas_scale(100., 50.), 1.);
+    }
+
+    #[test]
+    fn canvas_scale_is_integral() {
+        let widths = random_values(0x6c07_8965, 2000, 0., 8000.);
+        let heights = random_values(0x5bd1_e995, 2000, 0., 8000.);
+        for (width, height) in widths.zip(heights) {
+            let scale = compute_canvas_scale(width, height);
+            assert_eq!(scale, scale.trunc(), "scale {scale} for {width}x{height}");
+            assert!(scale >= 1.);
+        }
+    }
+
+    #[test]
+    fn canvas_scale_grows_with_the_window() {
+        let mut previous = compute_canvas_scale(0., 0.);
+        for size in 1..4000 {
+            let scale = compute_canvas_scale(size as f32 * 16. / 9., size as f32);
+            assert!(scale >= previous, "scale shrank at height {size}");
+            previous = scale;
+        }
+    }
+
+    #[test]
+    fn reflected_components_survive_a_scene_round_trip() {
+        use bevy::{ecs::entity::EntityHashMap, scene::{serde::SceneDeserializer, DynamicScene}};
+        use serde::de::DeserializeSeed;
+
+        let registry = AppTypeRegistry::default();
+        {
+            let mut registry = registry.write();
+            registry.register::<PixelCamera>();
+            registry.register::<HighResAnchor>();
+            registry.register::<CameraTarget>();
+            registry.register::<crate::PostProcessSettings>();
+            registry.register::<crate::PixelShadows>();
+        }
+        let mut world = World::new();
+        world.insert_resource(registry.clone());
+        let target = world.spawn(CameraTarget).id();
+        world.spawn((
+            PixelCamera{subpixel_position: Vec2::new(0.25, -0.5), scroll: ScrollMode::Locked},
+            crate::PostProcessSettings{color_depth: 3, dither: true, ..default()},
+            crate::PixelShadows{depth: 64., ..default()},
+        ));
+        world.spawn(HighResAnchor::Entity(target));
+
+        let ron = DynamicScene::from_world(&world).serialize(&registry.read()).unwrap();
+        let mut deserializer = ron::de::Deserializer::from_str(&ron).unwrap();
+        let scene = SceneDeserializer{type_registry: &registry.read()}.deserialize(&mut deserializer).unwrap();
+
+        // spawn a few entities first so the scene's ids don't line up by chance
+        let mut loaded = World::new();
+        loaded.insert_resource(registry.clone());
+        loaded.spawn_batch((0..5).map(|_| Transform::default()));
+        scene.write_to_world(&mut loaded, &mut EntityHashMap::default()).unwrap();
+
+        let camera = loaded.query::<&PixelCamera>().single(&loaded).unwrap();
+        assert_eq!(camera.subpixel_position, Vec2::new(0.25, -0.5));
+        assert_eq!(camera.scroll, ScrollMode::Locked);
+        let settings = loaded.query::<&crate::PostProcessSettings>().single(&loaded).unwrap();
+        assert_eq!((settings.color_depth, settings.dither), (3, true));
+        assert_eq!(loaded.query::<&crate::PixelShadows>().single(&loaded).unwrap().depth, 64.);
+
+        let target = loaded.query_filtered::<Entity, With<CameraTarget>>().single(&loaded).unwrap();
+        let HighResAnchor::Entity(anchored) = *loaded.query::<&HighResAnchor>().single(&loaded).unwrap() else {
+            panic!("the anchor lost its entity");
+        };
+        assert_eq!(anchored, target);
+    }
+}
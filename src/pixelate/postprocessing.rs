@@ -9,13 +9,13 @@ use bevy::{
     core_pipeline::{
         core_3d::graph::{Core3d, Node3d},
         fullscreen_vertex_shader::fullscreen_shader_vertex_state,
-        prepass::ViewPrepassTextures,
+        prepass::{DepthPrepass, NormalPrepass, ViewPrepassTextures},
     },
-    ecs::query::QueryItem,
+    ecs::{component::HookContext, query::QueryItem, world::DeferredWorld},
     prelude::*,
     render::{
         extract_component::{
-            ComponentUniforms, ExtractComponent, ExtractComponentPlugin, UniformComponentPlugin,
+            ComponentUniforms, DynamicUniformIndex, ExtractComponent, ExtractComponentPlugin, UniformComponentPlugin,
         },
         render_graph::{
             NodeRunError, RenderGraphApp, RenderGraphContext, RenderLabel, ViewNode, ViewNodeRunner
@@ -25,11 +25,12 @@ use bevy::{
             *,
         },
         renderer::{RenderContext, RenderDevice},
-        view::{ViewDepthTexture, ViewTarget, ViewUniform, ViewUniforms},
-        RenderApp,
+        view::{ViewTarget, ViewUniform, ViewUniformOffset, ViewUniforms},
+        Render, RenderApp, RenderSet,
     },
 };
 use binding_types::texture_depth_2d;
+use serde::{Deserialize, Serialize};
 
 /// It is generally encouraged to set up post processing effects as a plugin
 pub struct PostProcessPlugin;
@@ -37,6 +38,7 @@ pub struct PostProcessPlugin;
 impl Plugin for PostProcessPlugin {
     fn build(&self, app: &mut App) {
         app
+        .register_type::<PostProcessSettings>()
         .add_plugins((
             // The settings will be a component that lives in the main world but will
             // be extracted to the render world every frame.
@@ -45,10 +47,10 @@ impl Plugin for PostProcessPlugin {
             // It's important to derive [`ExtractComponent`] on [`PostProcessingSettings`]
             // for this plugin to work correctly.
             ExtractComponentPlugin::<PostProcessSettings>::default(),
-            // The settings will also be the data used in the shader.
-            // This plugin will prepare the component for the GPU by creating a uniform buffer
+            // Extracting the settings also produces a [`PostProcessUniform`] with the data used in the shader.
+            // This plugin will prepare that component for the GPU by creating a uniform buffer
             // and writing the data to that buffer every frame.
-            UniformComponentPlugin::<PostProcessSettings>::default(),
+            UniformComponentPlugin::<PostProcessUniform>::default(),
         ));
 
         // We need to get the render app from the main app
@@ -57,6 +59,16 @@ impl Plugin for PostProcessPlugin {
         };
 
         render_app
+            // The pipeline is specialized per view, so only the shader stages a camera
+            // actually uses get compiled.
+            .init_resource::<SpecializedRenderPipelines<PostProcessPipeline>>()
+            .add_systems(
+                Render,
+                (
+                    prepare_post_process_pipelines.in_set(RenderSet::Prepare),
+                    prepare_post_process_bind_groups.in_set(RenderSet::PrepareBindGroups),
+                ),
+            )
             // Bevy's renderer uses a render graph which is a collection of nodes in a directed acyclic graph.
             // It currently runs on each view/camera and executes each node in the specified order.
             // It will make sure that any node that needs a dependency from another node
@@ -115,57 +127,34 @@ impl ViewNode for PostProcessNode {
     // This query will only run on the view entity
     type ViewQuery = (
         &'static ViewTarget,
-        &'static PostProcessSettings,
-        &'static ViewDepthTexture,
-        &'static ViewPrepassTextures,
+        &'static ViewPostProcessPipeline,
+        &'static PostProcessBindGroups,
+        &'static DynamicUniformIndex<PostProcessUniform>,
+        &'static ViewUniformOffset,
     );
 
     fn run(
         &self,
         _graph: &mut RenderGraphContext,
         render_context: &mut RenderContext,
-        (view_target, _post_process_settings, depth, prepass): QueryItem<Self::ViewQuery>,
+        (view_target, view_pipeline, bind_groups, settings_index, view_uniform_offset): QueryItem<Self::ViewQuery>,
         world: &World,
     ) -> Result<(), NodeRunError> {
-        let post_process_pipeline = world.resource::<PostProcessPipeline>();
-
         let pipeline_cache = world.resource::<PipelineCache>();
 
-        let Some(pipeline) = pipeline_cache.get_render_pipeline(post_process_pipeline.pipeline_id)
+        let Some(pipeline) = pipeline_cache.get_render_pipeline(view_pipeline.pipeline_id)
         else {
             return Ok(());
         };
 
-        let settings_uniforms = world.resource::<ComponentUniforms<PostProcessSettings>>();
-        let Some(settings_binding) = settings_uniforms.uniforms().binding() else {
-            return Ok(());
-        };
-
         let post_process = view_target.post_process_write();
 
-        let Some(view_uniforms) = world.get_resource::<ViewUniforms>() else {
-            return Ok(());
-        };
-        let Some(view_uniform) = view_uniforms.uniforms.binding() else {
+        // The bind groups were built ahead of time for both sides of the main texture,
+        // pick the one that reads from the current source.
+        let Some(bind_group) = bind_groups.get(post_process.source) else {
             return Ok(());
         };
 
-        let bind_group = render_context.render_device().create_bind_group(
-            "post_process_bind_group",
-            &post_process_pipeline.layout,
-            &BindGroupEntries::sequential((
-                post_process.source,
-                post_process.source,
-                &post_process_pipeline.sampler,
-                settings_binding.clone(),
-                depth.view(),
-                prepass
-                    .normal_view()
-                    .expect("Make sure to add the NormalPrepass component to your camera"),
-                view_uniform.clone()
-            )),
-        );
-
         // Begin the render pass
         let mut render_pass = render_context.begin_tracked_render_pass(RenderPassDescriptor {
             label: Some("post_process_pass"),
@@ -180,7 +169,11 @@ impl ViewNode for PostProcessNode {
         });
 
         render_pass.set_render_pipeline(pipeline);
-        render_pass.set_bind_group(0, &bind_group, &[]);
+        render_pass.set_bind_group(
+            0,
+            bind_group,
+            &[settings_index.index(), view_uniform_offset.offset],
+        );
         render_pass.draw(0..3, 0..1);
 
         Ok(())
@@ -191,7 +184,7 @@ impl ViewNode for PostProcessNode {
 struct PostProcessPipeline {
     layout: BindGroupLayout,
     sampler: Sampler,
-    pipeline_id: CachedRenderPipelineId,
+    shader: Handle<Shader>,
 }
 
 impl FromWorld for PostProcessPipeline {
@@ -206,10 +199,10 @@ impl FromWorld for PostProcessPipeline {
                     texture_2d(TextureSampleType::Float { filterable: true }),
                     texture_2d(TextureSampleType::Float { filterable: true }),
                     sampler(SamplerBindingType::Filtering),
-                    uniform_buffer::<PostProcessSettings>(false),
+                    uniform_buffer::<PostProcessUniform>(true),
                     texture_depth_2d(),
                     texture_2d(TextureSampleType::Float { filterable: true }),
-                    uniform_buffer::<ViewUniform>(false).visibility(ShaderStages::VERTEX_FRAGMENT),
+                    uniform_buffer::<ViewUniform>(true).visibility(ShaderStages::VERTEX_FRAGMENT),
                 ),
             ),
         );
@@ -220,39 +213,366 @@ impl FromWorld for PostProcessPipeline {
             .resource::<AssetServer>()
             .load("shaders/post_processing.wgsl");
 
-        let pipeline_id = world
-            .resource_mut::<PipelineCache>()
-            .queue_render_pipeline(RenderPipelineDescriptor {
-                label: Some("post_process_pipeline".into()),
-                layout: vec![layout.clone()],
-                vertex: fullscreen_shader_vertex_state(),
-                fragment: Some(FragmentState {
-                    shader,
-                    shader_defs: vec![],
-                    entry_point: "fragment".into(),
-                    targets: vec![Some(ColorTargetState {
-                        format: TextureFormat::Rgba16Float,
-                        blend: None,
-                        write_mask: ColorWrites::ALL,
-                    })],
-                }),
-                primitive: PrimitiveState::default(),
-                depth_stencil: None,
-                multisample: MultisampleState::default(),
-                push_constant_ranges: vec![],
-                zero_initialize_workgroup_memory: true
-            });
-
         Self {
             layout,
             sampler,
-            pipeline_id,
+            shader,
+        }
+    }
+}
+
+/// The set of post-process features enabled for a view.
+/// Each distinct key compiles its own shader variant.
+#[derive(Debug, Hash, PartialEq, Eq, Clone, Copy)]
+pub struct PostProcessPipelineKey {
+    pub outline: bool,
+    pub palette: bool,
+    pub dither: bool,
+    pub debug_view: PixelDebugView,
+    /// Format of the view's main texture, which differs between HDR and LDR cameras.
+    pub texture_format: TextureFormat,
+}
+
+impl PostProcessPipelineKey {
+    fn from_settings(settings: &PostProcessSettings, texture_format: TextureFormat) -> Self {
+        Self {
+            outline: settings.outline,
+            palette: settings.palette,
+            dither: settings.dither,
+            debug_view: settings.debug_view,
+            texture_format,
+        }
+    }
+}
+
+impl SpecializedRenderPipeline for PostProcessPipeline {
+    type Key = PostProcessPipelineKey;
+
+    fn specialize(&self, key: Self::Key) -> RenderPipelineDescriptor {
+        let mut shader_defs = Vec::new();
+        if key.outline {
+            shader_defs.push("OUTLINE".into());
+        }
+        if key.palette {
+            shader_defs.push("PALETTE".into());
+        }
+        if key.dither {
+            shader_defs.push("DITHER".into());
+        }
+        if let Some(def) = key.debug_view.shader_def() {
+            shader_defs.push(def.into());
+        }
+
+        RenderPipelineDescriptor {
+            label: Some("post_process_pipeline".into()),
+            layout: vec![self.layout.clone()],
+            vertex: fullscreen_shader_vertex_state(),
+            fragment: Some(FragmentState {
+                shader: self.shader.clone(),
+                shader_defs,
+                entry_point: "fragment".into(),
+                targets: vec![Some(ColorTargetState {
+                    format: key.texture_format,
+                    blend: None,
+                    write_mask: ColorWrites::ALL,
+                })],
+            }),
+            primitive: PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: MultisampleState::default(),
+            push_constant_ranges: vec![],
+            zero_initialize_workgroup_memory: true
         }
     }
 }
 
-#[derive(Component, Default, Clone, Copy, ExtractComponent, ShaderType)]
+/// The specialized post-process pipeline for a view.
+#[derive(Component)]
+pub struct ViewPostProcessPipeline {
+    pub pipeline_id: CachedRenderPipelineId,
+}
+
+fn prepare_post_process_pipelines(
+    mut commands: Commands,
+    pipeline_cache: Res<PipelineCache>,
+    mut pipelines: ResMut<SpecializedRenderPipelines<PostProcessPipeline>>,
+    post_process_pipeline: Res<PostProcessPipeline>,
+    views: Query<(Entity, &ViewTarget, &PostProcessSettings)>,
+) {
+    for (entity, view_target, settings) in &views {
+        let pipeline_id = pipelines.specialize(
+            &pipeline_cache,
+            &post_process_pipeline,
+            PostProcessPipelineKey::from_settings(settings, view_target.main_texture_format()),
+        );
+
+        commands
+            .entity(entity)
+            .insert(ViewPostProcessPipeline { pipeline_id });
+    }
+}
+
+/// The resources a set of post-process bind groups was built from.
+#[derive(PartialEq, Eq, Clone, Copy)]
+struct PostProcessBindGroupKey {
+    main_texture: TextureViewId,
+    main_texture_other: TextureViewId,
+    depth: TextureViewId,
+    normal: TextureViewId,
+    settings: BufferId,
+    view: BufferId,
+}
+
+/// Cached post-process bind groups for a view, one per side of the ping-ponged main texture.
+/// They are only rebuilt when one of the underlying resources changes.
+#[derive(Component)]
+pub struct PostProcessBindGroups {
+    key: PostProcessBindGroupKey,
+    bind_groups: [(TextureViewId, BindGroup); 2],
+}
+
+impl PostProcessBindGroups {
+    /// The bind group that samples from `source`.
+    pub fn get(&self, source: &TextureView) -> Option<&BindGroup> {
+        self.bind_groups
+            .iter()
+            .find(|(id, _)| *id == source.id())
+            .map(|(_, bind_group)| bind_group)
+    }
+}
+
+#[allow(clippy::type_complexity)]
+fn prepare_post_process_bind_groups(
+    mut commands: Commands,
+    render_device: Res<RenderDevice>,
+    post_process_pipeline: Res<PostProcessPipeline>,
+    settings_uniforms: Res<ComponentUniforms<PostProcessUniform>>,
+    view_uniforms: Res<ViewUniforms>,
+    views: Query<(
+        Entity,
+        &ViewTarget,
+        Option<&ViewPrepassTextures>,
+        Option<&PostProcessBindGroups>,
+        &Msaa,
+    ), With<PostProcessSettings>>,
+) {
+    let (Some(settings_buffer), Some(view_buffer)) = (
+        settings_uniforms.uniforms().buffer(),
+        view_uniforms.uniforms.buffer(),
+    ) else {
+        return;
+    };
+    let (Some(settings_binding), Some(view_binding)) = (
+        settings_uniforms.uniforms().binding(),
+        view_uniforms.uniforms.binding(),
+    ) else {
+        return;
+    };
+
+    for (entity, view_target, prepass, cached, msaa) in &views {
+        // the layout expects single-sampled prepass textures
+        if msaa.samples() > 1 {
+            warn_once!("Skipping the pixel post-process for a camera with MSAA, set its Msaa to Off");
+            if cached.is_some() {
+                commands.entity(entity).remove::<PostProcessBindGroups>();
+            }
+            continue;
+        }
+        // `PostProcessSettings` requires both prepasses, but they can still be removed afterwards.
+        let Some((depth, normal)) = prepass.and_then(|prepass| prepass.depth_view().zip(prepass.normal_view())) else {
+            warn_once!("Skipping the pixel post-process for a camera without a DepthPrepass and NormalPrepass");
+            if cached.is_some() {
+                commands.entity(entity).remove::<PostProcessBindGroups>();
+            }
+            continue;
+        };
+
+        let key = PostProcessBindGroupKey {
+            main_texture: view_target.main_texture_view().id(),
+            main_texture_other: view_target.main_texture_other_view().id(),
+            depth: depth.id(),
+            normal: normal.id(),
+            settings: settings_buffer.id(),
+            view: view_buffer.id(),
+        };
+        if cached.is_some_and(|cached| cached.key == key) {
+            continue;
+        }
+
+        let create_bind_group = |source: &TextureView| {
+            render_device.create_bind_group(
+                "post_process_bind_group",
+                &post_process_pipeline.layout,
+                &BindGroupEntries::sequential((
+                    source,
+                    source,
+                    &post_process_pipeline.sampler,
+                    settings_binding.clone(),
+                    depth,
+                    normal,
+                    view_binding.clone(),
+                )),
+            )
+        };
+
+        let main_texture = view_target.main_texture_view();
+        let main_texture_other = view_target.main_texture_other_view();
+        commands.entity(entity).insert(PostProcessBindGroups {
+            key,
+            bind_groups: [
+                (main_texture.id(), create_bind_group(main_texture)),
+                (main_texture_other.id(), create_bind_group(main_texture_other)),
+            ],
+        });
+    }
+}
+
+/// Settings for the pixel post-process.
+/// The effect is opt-in: it only runs on cameras that have this component, and adding it
+/// also adds the depth and normal prepasses the outlines are computed from.
+///
+/// Toggling a stage or changing the debug view re-specializes the pipeline for the view
+/// instead of branching per pixel.
+#[derive(Component, Reflect, Clone, Copy, Debug, Serialize, Deserialize)]
+#[reflect(Component, Default)]
+#[serde(default)]
+#[require(DepthPrepass, NormalPrepass, Msaa::Off)]
+#[component(on_add = disable_msaa)]
 pub struct PostProcessSettings {
-    pub show_depth: u32,
-    pub show_normals: u32,
-}
\ No newline at end of file
+    /// Depth and normal based outlines.
+    pub outline: bool,
+    /// Colour quantization down to `color_depth` bits per channel.
+    pub palette: bool,
+    /// Ordered dithering applied before quantization.
+    pub dither: bool,
+    /// Bits per channel kept when `palette` is enabled.
+    pub color_depth: u32,
+    /// Which intermediate buffer to show instead of the final image.
+    pub debug_view: PixelDebugView,
+}
+
+impl Default for PostProcessSettings {
+    fn default() -> Self {
+        Self {
+            outline: true,
+            palette: true,
+            dither: false,
+            color_depth: 8,
+            debug_view: PixelDebugView::Final,
+        }
+    }
+}
+
+impl PostProcessSettings {
+    /// Steps above zero per channel of the palette, or `None` with `palette` off.
+    pub fn palette_levels(&self) -> Option<f32> {
+        self.palette.then(|| 2f32.powi(self.color_depth as i32) - 1.)
+    }
+}
+
+/// Rounds each colour channel to the nearest of `levels` steps in linear space, like the palette
+/// in the post-process, so colours drawn outside it match the quantized world.
+pub(crate) fn quantize_linear(color: LinearRgba, levels: f32) -> LinearRgba {
+    let quantize = |channel: f32| (channel * levels).round() / levels;
+    LinearRgba::new(quantize(color.red), quantize(color.green), quantize(color.blue), color.alpha)
+}
+
+/// Turns MSAA off on a camera getting [`PostProcessSettings`]. The required `Msaa::Off` only
+/// applies when nothing else provides one, and `Camera3d` requires the default 4x.
+fn disable_msaa(mut world: DeferredWorld, context: HookContext) {
+    if let Some(mut msaa) = world.get_mut::<Msaa>(context.entity) {
+        *msaa = Msaa::Off;
+    }
+}
+
+impl ExtractComponent for PostProcessSettings {
+    type QueryData = &'static Self;
+    type QueryFilter = With<Camera>;
+    type Out = (Self, PostProcessUniform);
+
+    fn extract_component(settings: QueryItem<'_, Self::QueryData>) -> Option<Self::Out> {
+        Some((
+            *settings,
+            PostProcessUniform {
+                color_depth: settings.color_depth,
+            },
+        ))
+    }
+}
+
+pub use uniform::PostProcessUniform;
+
+// The `ShaderType` derive emits per-field `check` functions that newer compilers flag as unused,
+// and an `allow` on the struct doesn't reach them, so the uniform gets its own module.
+mod uniform {
+    #![allow(dead_code)]
+
+    use bevy::{prelude::Component, render::render_resource::ShaderType};
+
+    /// The part of [`PostProcessSettings`](super::PostProcessSettings) the shader reads at runtime.
+    #[derive(Component, Clone, Copy, ShaderType)]
+    pub struct PostProcessUniform {
+        pub color_depth: u32,
+    }
+}
+
+/// What the post-process outputs, for inspecting the buffers the effect is built from.
+#[derive(Debug, Default, Hash, PartialEq, Eq, Clone, Copy, Reflect, Serialize, Deserialize)]
+#[reflect(Default)]
+pub enum PixelDebugView {
+    /// The finished image.
+    #[default]
+    Final,
+    /// Linear depth from the depth prepass.
+    LinearDepth,
+    /// Edges found from depth discontinuities.
+    DepthEdges,
+    /// Edges found from normal discontinuities.
+    NormalEdges,
+    /// The normal prepass as colour.
+    RawNormals,
+    /// The alpha of the normal prepass, which masks out surfaces that shouldn't be outlined.
+    OutlineMask,
+    /// The quantized luminance level each texel falls into, as a grey ramp.
+    PaletteIndex,
+    /// A colour per surface. There is no ID buffer, so this hashes each texel's normal and
+    /// depth; adjacent surfaces that face the same way at the same depth share a colour.
+    ObjectIds,
+    /// The final image with a checkerboard over alternating texels.
+    TexelGrid,
+}
+
+impl PixelDebugView {
+    /// Every view, in the order they are cycled through.
+    pub const ALL: [PixelDebugView; 9] = [
+        PixelDebugView::Final,
+        PixelDebugView::LinearDepth,
+        PixelDebugView::DepthEdges,
+        PixelDebugView::NormalEdges,
+        PixelDebugView::RawNormals,
+        PixelDebugView::OutlineMask,
+        PixelDebugView::PaletteIndex,
+        PixelDebugView::ObjectIds,
+        PixelDebugView::TexelGrid,
+    ];
+
+    /// The view after this one, wrapping back to [`PixelDebugView::Final`].
+    pub fn next(self) -> Self {
+        let index = Self::ALL.iter().position(|view| *view == self).unwrap_or(0);
+        Self::ALL[(index + 1) % Self::ALL.len()]
+    }
+
+    fn shader_def(self) -> Option<&'static str> {
+        match self {
+            PixelDebugView::Final => None,
+            PixelDebugView::LinearDepth => Some("DEBUG_LINEAR_DEPTH"),
+            PixelDebugView::DepthEdges => Some("DEBUG_DEPTH_EDGES"),
+            PixelDebugView::NormalEdges => Some("DEBUG_NORMAL_EDGES"),
+            PixelDebugView::RawNormals => Some("DEBUG_RAW_NORMALS"),
+            PixelDebugView::OutlineMask => Some("DEBUG_OUTLINE_MASK"),
+            PixelDebugView::PaletteIndex => Some("DEBUG_PALETTE_INDEX"),
+            PixelDebugView::ObjectIds => Some("DEBUG_OBJECT_IDS"),
+            PixelDebugView::TexelGrid => Some("DEBUG_TEXEL_GRID"),
+        }
+    }
+}
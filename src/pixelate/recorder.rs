@@ -0,0 +1,392 @@
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::BufWriter,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use bevy::{
+    prelude::*,
+    tasks::{block_on, poll_once, AsyncComputeTaskPool, Task},
+};
+use image::{
+    codecs::png::{CompressionType, FilterType as PngFilter, PngEncoder},
+    imageops::FilterType,
+    ImageEncoder, RgbImage,
+};
+
+use crate::{postprocessing::quantize_linear, screenshot::{capture_canvas, composite_ui}, PixelCamera, PixelCanvas, PixelDebugView, PixelUiCamera, PixelUiImage, PostProcessSettings};
+
+/// Starts recording the [`PixelCanvas`] at its native resolution, until a [`StopPixelRecording`].
+/// Frames are compressed as they arrive and kept in memory, up to [`max_duration`](Self::max_duration),
+/// then written when the recording stops, and a [`PixelRecordingSaved`] is sent.
+#[derive(Event, Clone, Debug)]
+pub struct StartPixelRecording {
+    /// The APNG file, or the directory for a frame sequence.
+    pub path: PathBuf,
+    pub format: RecordingFormat,
+    /// Frames captured per second. `None` captures every rendered frame.
+    pub fps: Option<f32>,
+    /// Integer nearest-neighbour upscale applied to every frame. 1 keeps the native size.
+    pub upscale: u32,
+    /// Seconds of frames kept. Older frames are dropped as new ones arrive, so the recording
+    /// ends up with the last `max_duration` seconds. `None` keeps every frame, however long it runs.
+    pub max_duration: Option<f32>,
+}
+
+/// Seconds of frames [`StartPixelRecording`] keeps by default.
+pub const DEFAULT_MAX_RECORDING_DURATION: f32 = 60.;
+
+/// How a recording is written.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Reflect)]
+pub enum RecordingFormat {
+    /// One animated PNG, which keeps every colour and plays in browsers.
+    Apng,
+    /// Numbered PNGs (`frame_0000.png`, ...) in a directory, for video editors and encoders.
+    Frames,
+}
+
+impl StartPixelRecording {
+    pub fn apng(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            format: RecordingFormat::Apng,
+            fps: None,
+            upscale: 1,
+            max_duration: Some(DEFAULT_MAX_RECORDING_DURATION),
+        }
+    }
+
+    pub fn frames(directory: impl Into<PathBuf>) -> Self {
+        Self {
+            format: RecordingFormat::Frames,
+            ..Self::apng(directory)
+        }
+    }
+
+    pub fn with_fps(mut self, fps: f32) -> Self {
+        self.fps = Some(fps);
+        self
+    }
+
+    pub fn with_upscale(mut self, upscale: u32) -> Self {
+        self.upscale = upscale;
+        self
+    }
+
+    pub fn with_max_duration(mut self, max_duration: Option<f32>) -> Self {
+        self.max_duration = max_duration;
+        self
+    }
+}
+
+/// Stops the current recording and writes it.
+#[derive(Event, Clone, Copy, Debug, Default)]
+pub struct StopPixelRecording;
+
+/// Sent when a recording has been written, or failed to be.
+#[derive(Event, Clone, Debug)]
+pub struct PixelRecordingSaved {
+    pub path: PathBuf,
+    pub frames: usize,
+    pub result: Result<(), String>,
+}
+
+/// Whether a recording is running; check it before sending [`StartPixelRecording`] or [`StopPixelRecording`].
+#[derive(Resource, Reflect, Default)]
+#[reflect(Resource, Default)]
+pub struct PixelRecorder {
+    #[reflect(ignore)]
+    recording: Option<Recording>,
+    #[reflect(ignore)]
+    saving: Vec<Task<PixelRecordingSaved>>,
+}
+
+impl PixelRecorder {
+    pub fn is_recording(&self) -> bool {
+        self.recording.as_ref().is_some_and(|recording| !recording.stopping)
+    }
+}
+
+/// A captured frame's index and timestamp, and the image as a PNG unless it couldn't be read back.
+type CapturedFrame = (usize, f64, Option<Vec<u8>>);
+
+/// The distinct colours of a recording and the index of each.
+type Palette = (Vec<[u8; 3]>, HashMap<[u8; 3], u8>);
+
+struct Recording {
+    request: StartPixelRecording,
+    /// Bits per channel of the post-process palette when the recording started, if the
+    /// post-process writes nothing but palette colours.
+    color_depth: Option<u32>,
+    /// Seconds since startup of the last capture.
+    last_capture: Option<f64>,
+    requested: usize,
+    /// Frames dropped for being older than the maximum duration.
+    dropped: usize,
+    /// Captured frames with their index and timestamp, in the order the GPU returned them.
+    frames: Arc<Mutex<Vec<CapturedFrame>>>,
+    stopping: bool,
+}
+
+pub struct PixelRecorderPlugin;
+
+impl Plugin for PixelRecorderPlugin {
+    fn build(&self, app: &mut App) {
+        app
+        .register_type::<RecordingFormat>()
+        .register_type::<PixelRecorder>()
+        .add_event::<StartPixelRecording>()
+        .add_event::<StopPixelRecording>()
+        .add_event::<PixelRecordingSaved>()
+        .init_resource::<PixelRecorder>()
+        .add_systems(Update, (start_and_stop_recordings, capture_frames, save_recordings).chain());
+    }
+}
+
+fn start_and_stop_recordings(
+    mut recorder: ResMut<PixelRecorder>,
+    mut starts: EventReader<StartPixelRecording>,
+    mut stops: EventReader<StopPixelRecording>,
+    post_process: Option<Single<&PostProcessSettings, With<PixelCamera>>>,
+    ui_camera: Query<(), With<PixelUiCamera>>,
+    ui_image: Option<Res<PixelUiImage>>,
+) {
+    // a HUD drawn straight into the canvas has colours of its own, so it can't be snapped to the palette
+    let hud_in_canvas = !ui_camera.is_empty() && ui_image.is_none();
+    if !stops.is_empty() {
+        stops.clear();
+        if let Some(recording) = &mut recorder.recording {
+            recording.stopping = true;
+        }
+    }
+    for start in starts.read() {
+        if recorder.recording.is_some() {
+            warn!("Already recording the canvas, ignoring a new recording to {}", start.path.display());
+            continue;
+        }
+        recorder.recording = Some(Recording {
+            request: start.clone(),
+            color_depth: post_process.as_ref().and_then(|settings| palette_depth(settings)).filter(|_| !hud_in_canvas),
+            last_capture: None,
+            requested: 0,
+            dropped: 0,
+            frames: default(),
+            stopping: false,
+        });
+    }
+}
+
+fn capture_frames(
+    mut commands: Commands,
+    mut recorder: ResMut<PixelRecorder>,
+    canvas: Option<Res<PixelCanvas>>,
+    ui: Option<Res<PixelUiImage>>,
+    time: Res<Time<Real>>,
+) {
+    let (Some(recording), Some(canvas)) = (&mut recorder.recording, canvas) else {
+        return;
+    };
+    if recording.stopping {
+        return;
+    }
+    let now = time.elapsed_secs_f64();
+    if let (Some(fps), Some(last)) = (recording.request.fps, recording.last_capture) {
+        if now - last < 1. / fps as f64 {
+            return;
+        }
+    }
+    recording.last_capture = Some(now);
+
+    if let Some(max_duration) = recording.request.max_duration {
+        let mut frames = recording.frames.lock().unwrap();
+        let count = frames.len();
+        frames.retain(|(_, time, _)| now - time < max_duration as f64);
+        recording.dropped += count - frames.len();
+    }
+
+    let index = recording.requested;
+    recording.requested += 1;
+    let frames = recording.frames.clone();
+    let color_depth = recording.color_depth;
+    capture_canvas(&mut commands, &canvas, ui.as_deref(), move |_, captured| {
+        // a raw frame of the canvas is large, so only keep it compressed
+        AsyncComputeTaskPool::get()
+            .spawn(async move {
+                let png = captured.and_then(|(mut image, ui)| {
+                    // the HUD isn't drawn in the palette, so snap the canvas before laying it over
+                    if let Some(depth) = color_depth {
+                        snap_to_palette(&mut image, depth);
+                    }
+                    if let Some(ui) = ui {
+                        composite_ui(&mut image, &ui);
+                    }
+                    compress_frame(&image)
+                });
+                if let Err(e) = &png {
+                    error!("Cannot read recorded canvas frame: {e}");
+                }
+                frames.lock().unwrap().push((index, now, png.ok()));
+            })
+            .detach();
+    });
+}
+
+fn save_recordings(
+    mut commands: Commands,
+    mut recorder: ResMut<PixelRecorder>,
+) {
+    // wait for the frames still on their way back from the GPU before writing
+    let finished = recorder.recording.as_ref().is_some_and(|recording| {
+        recording.stopping && recording.frames.lock().unwrap().len() + recording.dropped >= recording.requested
+    });
+    if finished {
+        let recording = recorder.recording.take().unwrap();
+        let mut frames = std::mem::take(&mut *recording.frames.lock().unwrap())
+            .into_iter()
+            .filter_map(|(index, time, image)| Some((index, time, image?)))
+            .collect::<Vec<_>>();
+        frames.sort_by_key(|(index, ..)| *index);
+        let request = recording.request;
+        let task = AsyncComputeTaskPool::get().spawn(async move {
+            let result = write_recording(&request, frames.as_slice());
+            PixelRecordingSaved {
+                path: request.path,
+                frames: frames.len(),
+                result,
+            }
+        });
+        recorder.saving.push(task);
+    }
+
+    recorder.saving.retain_mut(|task| {
+        let Some(saved) = block_on(poll_once(task)) else {
+            return true;
+        };
+        match &saved.result {
+            Ok(()) => info!("Recording of {} frames saved to {}", saved.frames, saved.path.display()),
+            Err(e) => error!("Cannot save recording to {}: {e}", saved.path.display()),
+        }
+        commands.send_event(saved);
+        false
+    });
+}
+
+fn write_recording(request: &StartPixelRecording, frames: &[(usize, f64, Vec<u8>)]) -> Result<(), String> {
+    if frames.is_empty() {
+        return Err("no frames were captured".into());
+    }
+    // frames are decoded one at a time, so the whole recording is never uncompressed at once
+    let image = |i: usize| -> Result<RgbImage, String> {
+        let mut image = image::load_from_memory(&frames[i].2).map_err(|e| e.to_string())?.to_rgb8();
+        if request.upscale > 1 {
+            let (width, height) = image.dimensions();
+            image = image::imageops::resize(&image, width * request.upscale, height * request.upscale, FilterType::Nearest);
+        }
+        Ok(image)
+    };
+
+    match request.format {
+        RecordingFormat::Frames => {
+            std::fs::create_dir_all(&request.path).map_err(|e| e.to_string())?;
+            for i in 0..frames.len() {
+                image(i)?.save(request.path.join(format!("frame_{i:04}.png"))).map_err(|e| e.to_string())?;
+            }
+            Ok(())
+        }
+        RecordingFormat::Apng => {
+            // each frame lasts until the next one was captured
+            let mut delays = frames.windows(2).map(|pair| pair[1].1 - pair[0].1).collect::<Vec<_>>();
+            delays.push(request.fps.map_or_else(|| delays.last().copied().unwrap_or(1. / 60.), |fps| 1. / fps as f64));
+            write_apng(&request.path, &delays, image)
+        }
+    }
+}
+
+/// Encodes a frame as a quickly compressed PNG to keep in memory until the recording is written.
+fn compress_frame(image: &RgbImage) -> Result<Vec<u8>, String> {
+    let mut png = Vec::new();
+    PngEncoder::new_with_quality(&mut png, CompressionType::Fast, PngFilter::Adaptive)
+        .write_image(image.as_raw(), image.width(), image.height(), image::ExtendedColorType::Rgb8)
+        .map_err(|e| e.to_string())?;
+    Ok(png)
+}
+
+/// The palette's bits per channel when every texel the post-process writes is a palette colour.
+/// The outline and the texel grid shade colours after they are quantized, so they leave it.
+fn palette_depth(settings: &PostProcessSettings) -> Option<u32> {
+    let exact = settings.palette && !settings.outline && settings.debug_view == PixelDebugView::Final;
+    exact.then_some(settings.color_depth)
+}
+
+/// Rounds every channel to the nearest level the post-process palette can produce, undoing any
+/// drift from the readback so the recording has exactly the palette's colours.
+fn snap_to_palette(image: &mut RgbImage, color_depth: u32) {
+    let levels = 2f32.powi(color_depth as i32) - 1.;
+    if levels < 1. {
+        return;
+    }
+    // the shader quantizes linear colour, the canvas stores it in sRGB
+    let nearest: [u8; 256] = std::array::from_fn(|value| {
+        let linear = LinearRgba::gray(Srgba::gamma_function(value as f32 / 255.));
+        (Srgba::gamma_function_inverse(quantize_linear(linear, levels).red) * 255.).round() as u8
+    });
+    for channel in image.iter_mut() {
+        *channel = nearest[*channel as usize];
+    }
+}
+
+/// Writes the frames `image` returns, one per delay, as an animated PNG.
+fn write_apng(path: &Path, delays: &[f64], image: impl Fn(usize) -> Result<RgbImage, String>) -> Result<(), String> {
+    if let Some(parent) = path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+        std::fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+    }
+    // palettes of 256 colours or less are stored indexed, which is smaller and exact
+    let palette = palette_of((0..delays.len()).map(&image))?;
+
+    let (width, height) = image(0)?.dimensions();
+    let file = File::create(path).map_err(|e| e.to_string())?;
+    let mut encoder = png::Encoder::new(BufWriter::new(file), width, height);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.set_animated(delays.len() as u32, 0).map_err(|e| e.to_string())?;
+    match &palette {
+        Some((colors, _)) => {
+            encoder.set_color(png::ColorType::Indexed);
+            encoder.set_palette(colors.iter().flatten().copied().collect::<Vec<_>>());
+        }
+        None => encoder.set_color(png::ColorType::Rgb),
+    }
+
+    let mut writer = encoder.write_header().map_err(|e| e.to_string())?;
+    for (i, delay) in delays.iter().enumerate() {
+        let image = image(i)?;
+        let millis = (delay * 1000.).round().clamp(1., u16::MAX as f64) as u16;
+        writer.set_frame_delay(millis, 1000).map_err(|e| e.to_string())?;
+        let data = match &palette {
+            Some((_, indices)) => image.pixels().map(|pixel| indices[&pixel.0]).collect(),
+            None => image.as_raw().clone(),
+        };
+        writer.write_image_data(&data).map_err(|e| e.to_string())?;
+    }
+    writer.finish().map_err(|e| e.to_string())
+}
+
+/// The colours used across all frames and their indices, if there are no more than 256.
+fn palette_of(images: impl Iterator<Item = Result<RgbImage, String>>) -> Result<Option<Palette>, String> {
+    let mut colors = Vec::new();
+    let mut indices = HashMap::new();
+    for image in images {
+        for pixel in image?.pixels() {
+            if indices.contains_key(&pixel.0) {
+                continue;
+            }
+            if colors.len() == 256 {
+                return Ok(None);
+            }
+            indices.insert(pixel.0, colors.len() as u8);
+            colors.push(pixel.0);
+        }
+    }
+    Ok(Some((colors, indices)))
+}
@@ -0,0 +1,190 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use bevy::{
+    prelude::*,
+    render::view::screenshot::{Screenshot, ScreenshotCaptured},
+};
+use image::{imageops::FilterType, RgbImage, RgbaImage};
+
+use crate::{PixelCanvas, PixelUiImage};
+
+/// Saves the [`PixelCanvas`] to a PNG at its native resolution, and optionally an upscaled copy.
+/// Works without a window; a [`PixelScreenshotSaved`] event is sent once the files are written.
+#[derive(Event, Clone, Debug)]
+pub struct PixelScreenshot {
+    pub path: PathBuf,
+    /// Integer factor for an extra nearest-neighbour upscaled copy, saved next to `path` with an `@<n>x` suffix.
+    /// 1 or less saves only the native image.
+    pub upscale: u32,
+}
+
+impl PixelScreenshot {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            upscale: 1,
+        }
+    }
+
+    pub fn with_upscale(mut self, upscale: u32) -> Self {
+        self.upscale = upscale;
+        self
+    }
+
+    /// Where the upscaled copy goes, e.g. `shots/title@4x.png` for `shots/title.png`.
+    pub fn upscaled_path(&self) -> PathBuf {
+        let stem = self.path.file_stem().unwrap_or_default().to_string_lossy();
+        self.path.with_file_name(format!("{stem}@{}x.png", self.upscale))
+    }
+}
+
+/// Sent when a [`PixelScreenshot`] has been written, or failed to be.
+#[derive(Event, Clone, Debug)]
+pub struct PixelScreenshotSaved {
+    pub path: PathBuf,
+    pub result: Result<(), String>,
+}
+
+pub struct PixelScreenshotPlugin;
+
+impl Plugin for PixelScreenshotPlugin {
+    fn build(&self, app: &mut App) {
+        app
+        .add_event::<PixelScreenshot>()
+        .add_event::<PixelScreenshotSaved>()
+        .add_systems(Update, take_pixel_screenshots);
+    }
+}
+
+fn take_pixel_screenshots(
+    mut commands: Commands,
+    mut requests: EventReader<PixelScreenshot>,
+    canvas: Option<Res<PixelCanvas>>,
+    ui: Option<Res<PixelUiImage>>,
+){
+    let Some(canvas) = canvas else {
+        return;
+    };
+    for request in requests.read() {
+        let request = request.clone();
+        capture_canvas(&mut commands, &canvas, ui.as_deref(), move |commands, captured| {
+            let result = captured.and_then(|(mut native, ui)| {
+                if let Some(ui) = ui {
+                    composite_ui(&mut native, &ui);
+                }
+                save_screenshot(&native, &request)
+            });
+            match &result {
+                Ok(()) => info!("Pixel screenshot saved to {}", request.path.display()),
+                Err(e) => error!("Cannot save pixel screenshot to {}: {e}", request.path.display()),
+            }
+            commands.send_event(PixelScreenshotSaved {
+                path: request.path.clone(),
+                result,
+            });
+        });
+    }
+}
+
+/// Reads the canvas back from the GPU, along with the HUD's texture when it has one of its own,
+/// and calls `captured` once both have arrived. Blend the HUD in with [`composite_ui`].
+pub(crate) fn capture_canvas(
+    commands: &mut Commands,
+    canvas: &PixelCanvas,
+    ui: Option<&PixelUiImage>,
+    captured: impl FnOnce(&mut Commands, Result<(RgbImage, Option<RgbaImage>), String>) + Send + Sync + 'static,
+) {
+    let images = std::iter::once(canvas.0.clone()).chain(ui.map(|ui| ui.0.clone())).collect::<Vec<_>>();
+    let parts = Arc::new(Mutex::new((vec![None; images.len()], Some(captured))));
+    for (slot, image) in images.into_iter().enumerate() {
+        let parts = parts.clone();
+        commands
+            .spawn(Screenshot::image(image))
+            .observe(move |trigger: Trigger<ScreenshotCaptured>, mut commands: Commands| {
+                let mut parts = parts.lock().unwrap();
+                parts.0[slot] = Some(trigger.event().0.clone());
+                if parts.0.iter().any(Option::is_none) {
+                    return;
+                }
+                let Some(captured) = parts.1.take() else {
+                    return;
+                };
+                let mut images = parts.0.drain(..).flatten().map(|image| image.try_into_dynamic().map_err(|e| e.to_string()));
+                // the canvas is drawn opaque, so its alpha channel is not coverage; keep the colour as it appears
+                let result = images.next().unwrap().and_then(|canvas| {
+                    let ui = images.next().transpose()?.map(|ui| ui.to_rgba8());
+                    Ok((canvas.to_rgb8(), ui))
+                });
+                captured(&mut commands, result);
+            });
+    }
+}
+
+/// Blends the HUD's premultiplied colour over the canvas the way the GPU does, in linear space.
+pub(crate) fn composite_ui(canvas: &mut RgbImage, ui: &RgbaImage) {
+    let linear: [f32; 256] = std::array::from_fn(|value| Srgba::gamma_function(value as f32 / 255.));
+    for (pixel, ui) in canvas.pixels_mut().zip(ui.pixels()) {
+        if ui.0 == [0; 4] {
+            continue;
+        }
+        let transparency = 1. - ui[3] as f32 / 255.;
+        for channel in 0..3 {
+            let value = linear[ui[channel] as usize] + linear[pixel[channel] as usize] * transparency;
+            pixel[channel] = (Srgba::gamma_function_inverse(value.min(1.)) * 255.).round() as u8;
+        }
+    }
+}
+
+fn save_screenshot(native: &RgbImage, request: &PixelScreenshot) -> Result<(), String> {
+    create_parent(&request.path)?;
+    native.save(&request.path).map_err(|e| e.to_string())?;
+
+    if request.upscale > 1 {
+        let (width, height) = native.dimensions();
+        let upscaled = image::imageops::resize(native, width * request.upscale, height * request.upscale, FilterType::Nearest);
+        upscaled.save(request.upscaled_path()).map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+fn create_parent(path: &Path) -> Result<(), String> {
+    match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => std::fs::create_dir_all(parent).map_err(|e| e.to_string()),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use image::{Rgb, Rgba};
+
+    use super::*;
+
+    #[test]
+    fn transparent_ui_keeps_the_canvas() {
+        let mut canvas = RgbImage::from_pixel(2, 1, Rgb([200, 100, 50]));
+        composite_ui(&mut canvas, &RgbaImage::new(2, 1));
+        assert_eq!(canvas, RgbImage::from_pixel(2, 1, Rgb([200, 100, 50])));
+    }
+
+    #[test]
+    fn opaque_ui_replaces_the_canvas() {
+        let mut canvas = RgbImage::from_pixel(1, 1, Rgb([200, 100, 50]));
+        composite_ui(&mut canvas, &RgbaImage::from_pixel(1, 1, Rgba([10, 20, 30, 255])));
+        assert_eq!(canvas.get_pixel(0, 0), &Rgb([10, 20, 30]));
+    }
+
+    #[test]
+    fn translucent_ui_blends_in_linear_space() {
+        // half-transparent white, premultiplied in linear space and stored as sRGB
+        let half = (Srgba::gamma_function_inverse(0.5) * 255.).round() as u8;
+        let mut canvas = RgbImage::from_pixel(2, 1, Rgb([0, 0, 0]));
+        canvas.put_pixel(1, 0, Rgb([255, 255, 255]));
+        composite_ui(&mut canvas, &RgbaImage::from_pixel(2, 1, Rgba([half, half, half, 128])));
+        assert_eq!(canvas.get_pixel(0, 0), &Rgb([half; 3]));
+        assert_eq!(canvas.get_pixel(1, 0), &Rgb([255; 3]));
+    }
+}
@@ -0,0 +1,110 @@
+use bevy::{
+    pbr::{CascadeShadowConfigBuilder, DirectionalLightShadowMap, ShadowFilteringMethod},
+    prelude::*,
+    render::renderer::RenderDevice,
+};
+
+use crate::{PixelCamera, RES_WIDTH};
+
+/// Crisp shadows for the [`PixelCamera`]. Add it next to the camera.
+/// Dithered penumbras are a material option, see [`PixelToonExtension::dither_shadows`](crate::PixelToonExtension::dither_shadows).
+#[derive(Component, Reflect, Clone, Copy, Debug)]
+#[reflect(Component, Default)]
+pub struct PixelShadows {
+    /// Fits each directional light with a [`PixelShadowLight`] to a single cascade sized so one shadow map
+    /// texel covers one canvas texel, and sets the global `DirectionalLightShadowMap` size to match.
+    /// Bevy keeps cascades aligned to their own texels, so shadow edges step with the canvas instead of sliding.
+    /// When that map would be larger than the GPU allows, a shadow texel covers several canvas texels instead.
+    pub snap_to_texels: bool,
+    /// Filters the shadow map with a single hardware 2x2 PCF lookup instead of the Gaussian filter.
+    /// Edges still blend across one shadow texel, which `dither_shadows` can turn into a dither.
+    pub hard: bool,
+    /// Depth of the slab around the camera that receives shadows, in world units.
+    /// The pixel camera is pulled back by half of it so the slab is centred on the scene.
+    pub depth: f32,
+}
+
+impl Default for PixelShadows {
+    fn default() -> Self {
+        Self {
+            snap_to_texels: true,
+            hard: true,
+            depth: 256.,
+        }
+    }
+}
+
+/// Opts a directional light into [`PixelShadows::snap_to_texels`]. Lights without it keep their own
+/// `CascadeShadowConfig`, and the global shadow map is left alone while no light has it.
+#[derive(Component, Reflect, Clone, Copy, Debug, Default)]
+#[reflect(Component, Default)]
+pub struct PixelShadowLight;
+
+/// Shadow map size used when the render device isn't available to ask, the WebGL2 limit.
+const MAX_SHADOW_MAP_SIZE: u32 = 4096;
+
+pub struct PixelShadowsPlugin;
+
+impl Plugin for PixelShadowsPlugin {
+    fn build(&self, app: &mut App) {
+        app
+        .register_type::<PixelShadows>()
+        .register_type::<PixelShadowLight>()
+        .add_systems(PostUpdate, fit_shadows.after(bevy::render::camera::CameraUpdateSystem));
+    }
+}
+
+/// Sizes the shadow cascade and filtering to match the [`PixelCamera`]'s texels.
+fn fit_shadows(
+    mut commands: Commands,
+    cam: Single<(Entity, Ref<PixelShadows>, Ref<Projection>), With<PixelCamera>>,
+    lights: Query<Entity, (With<DirectionalLight>, With<PixelShadowLight>)>,
+    new_lights: Query<(), (With<DirectionalLight>, Added<PixelShadowLight>)>,
+    mut shadow_map: ResMut<DirectionalLightShadowMap>,
+    device: Option<Res<RenderDevice>>,
+){
+    let (entity, shadows, projection) = cam.into_inner();
+    if !shadows.is_changed() && !projection.is_changed() && new_lights.is_empty() {
+        return;
+    }
+
+    commands.entity(entity).insert(if shadows.hard {
+        ShadowFilteringMethod::Hardware2x2
+    } else {
+        ShadowFilteringMethod::Gaussian
+    });
+
+    let Projection::Orthographic(ortho) = projection.as_ref() else {
+        return;
+    };
+    let area = ortho.area.size();
+    if !shadows.snap_to_texels || area.x <= 0. || shadows.depth <= 0. || lights.is_empty() {
+        return;
+    }
+
+    // Bevy sizes the cascade to the diagonal of the slab it covers
+    let canvas_texel = area.x / RES_WIDTH as f32;
+    let diameter = area.extend(shadows.depth).length().ceil();
+    // a whole number of canvas texels per shadow texel keeps the edges on the canvas grid
+    let max_size = device.map_or(MAX_SHADOW_MAP_SIZE, |device| device.limits().max_texture_dimension_2d) as f32;
+    let coarsen = (diameter / canvas_texel / max_size).ceil().max(1.);
+    if coarsen > 1. {
+        warn_once!("The pixel shadow map would exceed {max_size} texels, using {coarsen} canvas texels per shadow texel");
+    }
+    let size = (diameter / (canvas_texel * coarsen)).round() as usize;
+    if shadow_map.size != size {
+        shadow_map.size = size;
+    }
+
+    let cascades = CascadeShadowConfigBuilder {
+        num_cascades: 1,
+        minimum_distance: 0.,
+        maximum_distance: shadows.depth,
+        first_cascade_far_bound: shadows.depth,
+        overlap_proportion: 0.,
+    }
+    .build();
+    for light in &lights {
+        commands.entity(light).insert(cascades.clone());
+    }
+}
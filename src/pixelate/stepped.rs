@@ -0,0 +1,125 @@
+use std::time::Duration;
+
+use bevy::{
+    animation::{advance_animations, animate_targets, graph::AnimationNodeIndex},
+    prelude::*,
+};
+
+/// Moves an entity's animation at a low, fixed framerate, like hand-drawn pixel art, while the game runs at full rate.
+///
+/// If the entity has an [`AnimationPlayer`], its clips are sampled only on steps.
+/// Tweens written as systems can do the same by advancing with [`delta`](Self::delta) instead of [`Time`].
+#[derive(Component, Reflect, Clone, Debug)]
+#[reflect(Component, Default)]
+pub struct SteppedAnimation {
+    /// Poses per second. 0 disables stepping.
+    pub fps: f32,
+    accumulated: Duration,
+    delta: Duration,
+    /// Speeds of the player's animations while they are changed for a step.
+    #[reflect(ignore)]
+    speeds: Vec<(AnimationNodeIndex, f32)>,
+}
+
+impl Default for SteppedAnimation {
+    fn default() -> Self {
+        Self::on_twos()
+    }
+}
+
+impl SteppedAnimation {
+    pub fn new(fps: f32) -> Self {
+        Self {
+            fps,
+            accumulated: Duration::ZERO,
+            delta: Duration::ZERO,
+            speeds: Vec::new(),
+        }
+    }
+
+    /// 12 poses per second, every other frame of a 24 fps animation.
+    pub fn on_twos() -> Self {
+        Self::new(12.)
+    }
+
+    /// Time to advance the animation by this frame: whole steps on a step, zero in between.
+    pub fn delta(&self) -> Duration {
+        self.delta
+    }
+
+    pub fn delta_secs(&self) -> f32 {
+        self.delta.as_secs_f32()
+    }
+
+    /// Whether the animation moves this frame.
+    pub fn just_stepped(&self) -> bool {
+        !self.delta.is_zero()
+    }
+
+    fn tick(&mut self, delta: Duration) {
+        if self.fps <= 0. {
+            self.delta = delta;
+            return;
+        }
+        let step = Duration::from_secs_f32(1. / self.fps);
+        self.accumulated += delta;
+        let steps = (self.accumulated.as_secs_f32() * self.fps).floor() as u32;
+        self.delta = step * steps;
+        self.accumulated = self.accumulated.saturating_sub(self.delta);
+    }
+}
+
+pub struct SteppedAnimationPlugin;
+
+impl Plugin for SteppedAnimationPlugin {
+    fn build(&self, app: &mut App) {
+        app
+        .register_type::<SteppedAnimation>()
+        .add_systems(PreUpdate, tick_stepped_animations)
+        .add_systems(PostUpdate, (
+            step_animation_players.before(advance_animations),
+            restore_animation_speeds.after(advance_animations).before(animate_targets),
+        ));
+    }
+}
+
+fn tick_stepped_animations(
+    time: Res<Time>,
+    mut stepped: Query<&mut SteppedAnimation>,
+){
+    for mut stepped in &mut stepped {
+        stepped.tick(time.delta());
+    }
+}
+
+/// Scales the player's speeds so it advances by the stepped delta instead of the frame time.
+fn step_animation_players(
+    time: Res<Time>,
+    mut players: Query<(&mut SteppedAnimation, &mut AnimationPlayer)>,
+){
+    let frame = time.delta_secs();
+    if frame <= 0. {
+        return;
+    }
+    for (mut stepped, mut player) in &mut players {
+        let factor = stepped.delta_secs() / frame;
+        stepped.speeds.clear();
+        for (&index, animation) in player.playing_animations_mut() {
+            let speed = animation.speed();
+            stepped.speeds.push((index, speed));
+            animation.set_speed(speed * factor);
+        }
+    }
+}
+
+fn restore_animation_speeds(
+    mut players: Query<(&mut SteppedAnimation, &mut AnimationPlayer)>,
+){
+    for (mut stepped, mut player) in &mut players {
+        for (index, speed) in stepped.speeds.drain(..) {
+            if let Some(animation) = player.animation_mut(index) {
+                animation.set_speed(speed);
+            }
+        }
+    }
+}
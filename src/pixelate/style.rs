@@ -0,0 +1,179 @@
+use std::path::PathBuf;
+
+use bevy::{
+    asset::{io::Reader, AssetLoader, LoadContext},
+    prelude::*,
+};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{PixelCamSettings, PixelCamera, PostProcessSettings, WindowSize};
+
+/// A post-process and camera setup loaded from a `.pixelstyle.ron` file.
+/// Attach it to a camera with [`PixelStyleHandle`]; edits to the file are applied
+/// while the game runs when Bevy's `file_watcher` feature is enabled.
+#[derive(Asset, Reflect, Serialize, Deserialize, Clone, Debug)]
+#[reflect(Default)]
+#[serde(default)]
+pub struct PixelStyle {
+    pub post_process: PostProcessSettings,
+    /// Zoom of the pixel camera, see [`WindowSize::zoom`].
+    pub zoom: f32,
+    /// Whether the camera renders in HDR, see [`PixelCamSettings::hdr`].
+    pub hdr: bool,
+}
+
+impl Default for PixelStyle {
+    fn default() -> Self {
+        Self {
+            post_process: PostProcessSettings::default(),
+            zoom: 5.,
+            hdr: true,
+        }
+    }
+}
+
+impl PixelStyle {
+    /// Pretty-printed RON, in the format [`PixelStyleLoader`] reads.
+    pub fn to_ron(&self) -> Result<String, ron::Error> {
+        ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())
+    }
+}
+
+/// The [`PixelStyle`] a camera takes its settings from.
+#[derive(Component, Reflect, Clone, Default)]
+#[reflect(Component, Default)]
+pub struct PixelStyleHandle(pub Handle<PixelStyle>);
+
+/// Writes the current settings of `camera` to `path` as a [`PixelStyle`].
+#[derive(Event)]
+pub struct SavePixelStyle {
+    pub camera: Entity,
+    pub path: PathBuf,
+}
+
+#[derive(Debug, Error)]
+pub enum PixelStyleLoaderError {
+    #[error("could not read pixel style: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("could not parse pixel style: {0}")]
+    Ron(#[from] ron::error::SpannedError),
+}
+
+#[derive(Default)]
+pub struct PixelStyleLoader;
+
+impl AssetLoader for PixelStyleLoader {
+    type Asset = PixelStyle;
+    type Settings = ();
+    type Error = PixelStyleLoaderError;
+
+    async fn load(
+        &self,
+        reader: &mut dyn Reader,
+        _settings: &(),
+        _load_context: &mut LoadContext<'_>,
+    ) -> Result<PixelStyle, PixelStyleLoaderError> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).await?;
+        Ok(ron::de::from_bytes(&bytes)?)
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["pixelstyle.ron"]
+    }
+}
+
+pub struct PixelStylePlugin;
+
+impl Plugin for PixelStylePlugin {
+    fn build(&self, app: &mut App) {
+        app
+        .init_asset::<PixelStyle>()
+        .register_asset_reflect::<PixelStyle>()
+        .register_type::<PixelStyleHandle>()
+        .init_asset_loader::<PixelStyleLoader>()
+        .add_event::<SavePixelStyle>()
+        .add_systems(Update, (apply_pixel_style, save_pixel_style));
+    }
+}
+
+/// Copies a [`PixelStyle`] onto the cameras using it whenever the handle or the asset changes.
+#[allow(clippy::type_complexity)]
+fn apply_pixel_style(
+    mut events: EventReader<AssetEvent<PixelStyle>>,
+    styles: Res<Assets<PixelStyle>>,
+    mut cameras: Query<(
+        Ref<PixelStyleHandle>,
+        &mut PostProcessSettings,
+        &mut Camera,
+        &mut Projection,
+        Has<PixelCamera>,
+    )>,
+    mut window: Option<ResMut<WindowSize>>,
+    mut cam_settings: Option<ResMut<PixelCamSettings>>,
+) {
+    let changed: Vec<AssetId<PixelStyle>> = events
+        .read()
+        .filter_map(|event| match event {
+            AssetEvent::LoadedWithDependencies { id } | AssetEvent::Modified { id } => Some(*id),
+            _ => None,
+        })
+        .collect();
+
+    for (handle, mut settings, mut camera, mut projection, is_pixel_camera) in &mut cameras {
+        if !handle.is_changed() && !changed.contains(&handle.0.id()) {
+            continue;
+        }
+        let Some(style) = styles.get(&handle.0) else {
+            continue;
+        };
+
+        *settings = style.post_process;
+        if is_pixel_camera {
+            // the zoom and HDR are shared with the canvas, so they go through the resources
+            if let Some(window) = window.as_mut() {
+                window.zoom = style.zoom;
+            }
+            if let Some(cam_settings) = cam_settings.as_mut() {
+                cam_settings.hdr = style.hdr;
+            }
+            *projection = Projection::Orthographic(OrthographicProjection {
+                scale: 1. / style.zoom,
+                far: 10000.,
+                near: -1000.,
+                ..OrthographicProjection::default_3d()
+            });
+        } else {
+            camera.hdr = style.hdr;
+        }
+    }
+}
+
+fn save_pixel_style(
+    mut events: EventReader<SavePixelStyle>,
+    cameras: Query<(&PostProcessSettings, &Camera, Has<PixelCamera>)>,
+    window: Option<Res<WindowSize>>,
+) {
+    for event in events.read() {
+        let Ok((settings, camera, is_pixel_camera)) = cameras.get(event.camera) else {
+            warn!("Can't save a pixel style for {}, it has no PostProcessSettings", event.camera);
+            continue;
+        };
+        let style = PixelStyle {
+            post_process: *settings,
+            zoom: match &window {
+                Some(window) if is_pixel_camera => window.zoom,
+                _ => PixelStyle::default().zoom,
+            },
+            hdr: camera.hdr,
+        };
+        let result = style
+            .to_ron()
+            .map_err(|err| err.to_string())
+            .and_then(|ron| std::fs::write(&event.path, ron).map_err(|err| err.to_string()));
+        if let Err(err) = result {
+            error!("Failed to save pixel style to {}: {err}", event.path.display());
+        }
+    }
+}
@@ -0,0 +1,104 @@
+use bevy::{
+    pbr::{ExtendedMaterial, MaterialExtension, MaterialExtensionKey, MaterialExtensionPipeline},
+    prelude::*,
+    render::{
+        mesh::MeshVertexBufferLayoutRef,
+        render_resource::{AsBindGroup, RenderPipelineDescriptor, ShaderRef, SpecializedMeshPipelineError},
+    },
+};
+
+/// A [`StandardMaterial`] lit in flat bands, so the post-process quantization has clean steps to work with.
+/// The prepass is the standard one, so outlines work the same as on any other mesh.
+pub type PixelToonMaterial = ExtendedMaterial<StandardMaterial, PixelToonExtension>;
+
+/// Toon lighting on top of a [`StandardMaterial`], see [`PixelToonMaterial`].
+#[derive(Asset, AsBindGroup, Reflect, Debug, Clone)]
+#[bind_group_data(PixelToonKey)]
+pub struct PixelToonExtension {
+    /// Number of diffuse lighting bands.
+    #[uniform(100)]
+    pub bands: u32,
+    /// Brightness of the specular highlight, 0 disables it.
+    #[uniform(100)]
+    pub specular_strength: f32,
+    /// How large the hard-edged specular highlight is, from 0 to 1.
+    #[uniform(100)]
+    pub specular_size: f32,
+    /// Brightness of the rim light, 0 disables it.
+    #[uniform(100)]
+    pub rim_strength: f32,
+    /// How far the rim light reaches in from the silhouette, from 0 to 1.
+    #[uniform(100)]
+    pub rim_size: f32,
+    #[uniform(100)]
+    pub rim_color: LinearRgba,
+    /// Optional horizontal ramp the bands are looked up in, from unlit on the left to fully lit on the right.
+    #[texture(101)]
+    #[sampler(102)]
+    pub ramp: Option<Handle<Image>>,
+    /// Dithers the soft edge of the main directional light's shadow instead of blending it.
+    pub dither_shadows: bool,
+}
+
+impl Default for PixelToonExtension {
+    fn default() -> Self {
+        Self {
+            bands: 3,
+            specular_strength: 0.5,
+            specular_size: 0.1,
+            rim_strength: 0.3,
+            rim_size: 0.2,
+            rim_color: LinearRgba::WHITE,
+            ramp: None,
+            dither_shadows: false,
+        }
+    }
+}
+
+#[derive(Debug, Hash, PartialEq, Eq, Clone, Copy)]
+pub struct PixelToonKey {
+    ramp: bool,
+    dither_shadows: bool,
+}
+
+impl From<&PixelToonExtension> for PixelToonKey {
+    fn from(extension: &PixelToonExtension) -> Self {
+        Self {
+            ramp: extension.ramp.is_some(),
+            dither_shadows: extension.dither_shadows,
+        }
+    }
+}
+
+impl MaterialExtension for PixelToonExtension {
+    fn fragment_shader() -> ShaderRef {
+        "shaders/pixel_toon.wgsl".into()
+    }
+
+    fn specialize(
+        _pipeline: &MaterialExtensionPipeline,
+        descriptor: &mut RenderPipelineDescriptor,
+        _layout: &MeshVertexBufferLayoutRef,
+        key: MaterialExtensionKey<Self>,
+    ) -> Result<(), SpecializedMeshPipelineError> {
+        if let Some(fragment) = descriptor.fragment.as_mut() {
+            if key.bind_group_data.ramp {
+                fragment.shader_defs.push("TOON_RAMP".into());
+            }
+            if key.bind_group_data.dither_shadows {
+                fragment.shader_defs.push("TOON_DITHER_SHADOWS".into());
+            }
+        }
+        Ok(())
+    }
+}
+
+pub struct PixelToonPlugin;
+
+impl Plugin for PixelToonPlugin {
+    fn build(&self, app: &mut App) {
+        app
+        .register_type::<PixelToonExtension>()
+        .add_plugins(MaterialPlugin::<PixelToonMaterial>::default());
+    }
+}
@@ -0,0 +1,74 @@
+use bevy::{
+    ecs::{component::HookContext, world::DeferredWorld},
+    prelude::*,
+    render::render_resource::{Extent3d, TextureDescriptor, TextureDimension, TextureFormat, TextureUsages},
+};
+
+use crate::PostProcessSettings;
+
+/// A secondary pixelated camera, such as a minimap or an in-world monitor, rendering to its own
+/// low-resolution [`Image`] with the post-process.
+///
+/// Create it with [`PixelView::new`] and use [`image`](Self::image) on a material or an `ImageNode`.
+/// Add `RenderLayers` to choose what it sees and a [`Projection`] to change from perspective.
+/// Views render before the [`PixelCamera`](crate::PixelCamera), so it sees them in the same frame.
+#[derive(Component, Reflect, Clone, Debug)]
+#[reflect(Component)]
+#[require(Camera3d, PostProcessSettings)]
+#[component(on_add = setup_pixel_view)]
+pub struct PixelView {
+    pub image: Handle<Image>,
+}
+
+impl PixelView {
+    /// Creates the view's `width` x `height` image in `images`.
+    pub fn new(images: &mut Assets<Image>, width: u32, height: u32) -> Self {
+        let size = Extent3d {
+            width,
+            height,
+            ..default()
+        };
+        let mut image = Image {
+            texture_descriptor: TextureDescriptor {
+                label: None,
+                size,
+                dimension: TextureDimension::D2,
+                format: TextureFormat::Bgra8UnormSrgb,
+                mip_level_count: 1,
+                sample_count: 1,
+                usage: TextureUsages::TEXTURE_BINDING
+                    | TextureUsages::COPY_DST
+                    | TextureUsages::RENDER_ATTACHMENT,
+                view_formats: &[],
+            },
+            ..default()
+        };
+        image.resize(size);
+        Self {
+            image: images.add(image),
+        }
+    }
+}
+
+pub struct PixelViewPlugin;
+
+impl Plugin for PixelViewPlugin {
+    fn build(&self, app: &mut App) {
+        app
+        .register_type::<PixelView>();
+    }
+}
+
+/// Points a new view at its image before it first renders.
+fn setup_pixel_view(mut world: DeferredWorld, context: HookContext) {
+    let Some(image) = world.get::<PixelView>(context.entity).map(|view| view.image.clone()) else {
+        return;
+    };
+    if let Some(mut camera) = world.get_mut::<Camera>(context.entity) {
+        camera.target = image.into();
+        // before the pixel camera, which is at -1
+        if camera.order == 0 {
+            camera.order = -2;
+        }
+    }
+}
@@ -1,48 +1,399 @@
+use std::f32::consts::TAU;
 
-use bevy::prelude::*;
-use crate::pixel_cam::*;
+use bevy::{
+    picking::mesh_picking::ray_cast::{MeshRayCast, MeshRayCastSettings, RayCastVisibility},
+    platform::collections::HashSet,
+    prelude::*,
+};
+use crate::{pixel_cam::*, PixelAction, PixelInput, PixelInputMap, SteppedAnimation, input::init_pixel_input};
 
 pub struct PlayerPlugin;
 
-#[derive(Component)]
-pub struct Player{
-    pub x: f32,
-    pub y: f32,
+#[derive(Component, Reflect, Default)]
+#[reflect(Component, Default)]
+#[require(CharacterController)]
+pub struct Player;
+
+/// Marks a static mesh the [`CharacterController`] collides with.
+#[derive(Component, Reflect, Default)]
+#[reflect(Component, Default)]
+pub struct StaticCollider;
+
+/// Kinematic capsule that walks on and slides along [`StaticCollider`] meshes.
+/// The entity's translation is the centre of the capsule.
+///
+/// Collisions are found with rays rather than a shape cast: walls with rays across the capsule's
+/// width at its step height, middle and head, and the ground and ceiling with one ray down its axis.
+/// Obstacles thinner than the gaps between the rays can be clipped.
+#[derive(Component, Reflect, Clone, Debug)]
+#[reflect(Component, Default)]
+pub struct CharacterController {
+    pub radius: f32,
+    /// Full height of the capsule.
+    pub height: f32,
+    /// Ledges up to this high are walked onto.
+    pub step_height: f32,
+    /// Steepest walkable slope, in radians.
+    pub max_slope: f32,
+    pub gravity: f32,
+    pub jump_speed: f32,
+    /// How fast the character turns to face where it moves, in radians per second.
+    pub turn_speed: f32,
+    pub velocity: Vec3,
+    pub grounded: bool,
+}
+
+impl Default for CharacterController {
+    fn default() -> Self {
+        Self {
+            radius: 6.,
+            height: 24.,
+            step_height: 3.,
+            max_slope: 45f32.to_radians(),
+            gravity: 200.,
+            jump_speed: 80.,
+            turn_speed: 10.,
+            velocity: Vec3::ZERO,
+            grounded: false,
+        }
+    }
+}
+
+/// Moves the [`Player`] one tile at a time on a world grid instead of with the [`CharacterController`].
+/// Input is taken relative to the camera and snapped to the grid's 4 or 8 directions.
+/// Motion between tiles is interpolated, in steps if the entity has a [`SteppedAnimation`].
+#[derive(Component, Reflect, Clone, Debug)]
+#[reflect(Component, Default)]
+#[require(GridPosition)]
+pub struct GridMovement {
+    /// Size of a tile on the X and Z axes. Tile centres are at multiples of it.
+    pub tile_size: Vec2,
+    /// 4 for orthogonal moves only, 8 to allow diagonals.
+    pub directions: u32,
+    /// Seconds to move one tile.
+    pub step_duration: f32,
+}
+
+impl Default for GridMovement {
+    fn default() -> Self {
+        Self {
+            tile_size: Vec2::splat(15.),
+            directions: 4,
+            step_duration: 0.2,
+        }
+    }
+}
+
+/// Where a [`GridMovement`] entity is on the grid. Set from its translation when it's added.
+#[derive(Component, Reflect, Default, Clone, Debug)]
+#[reflect(Component, Default)]
+pub struct GridPosition {
+    pub tile: IVec2,
+    /// Grid direction the entity faces.
+    pub facing: IVec2,
+    /// Tile it is moving to, if any.
+    pub target: Option<IVec2>,
+    progress: f32,
+    /// Direction pressed during a move, taken when it arrives.
+    buffered: Option<IVec2>,
 }
 
+/// Tiles a [`GridMovement`] entity can't move onto.
+#[derive(Resource, Reflect, Default, Clone, Debug)]
+#[reflect(Resource, Default)]
+pub struct BlockedTiles(pub HashSet<IVec2>);
+
+impl BlockedTiles {
+    pub fn is_blocked(&self, tile: IVec2) -> bool {
+        self.0.contains(&tile)
+    }
+
+    pub fn block(&mut self, tile: IVec2) {
+        self.0.insert(tile);
+    }
+
+    pub fn unblock(&mut self, tile: IVec2) {
+        self.0.remove(&tile);
+    }
+
+    /// Whether a move from `from` by `dir` is allowed. Diagonals can't cut blocked corners.
+    pub fn can_move(&self, from: IVec2, dir: IVec2) -> bool {
+        let to = from + dir;
+        if self.is_blocked(to) {
+            return false;
+        }
+        dir.x == 0 || dir.y == 0 || !(self.is_blocked(from + IVec2::new(dir.x, 0)) || self.is_blocked(from + IVec2::new(0, dir.y)))
+    }
+}
+
+impl GridMovement {
+    /// World position of a tile's centre on the X and Z axes.
+    pub fn tile_center(&self, tile: IVec2) -> Vec2 {
+        tile.as_vec2() * self.tile_size
+    }
+
+    /// Tile that contains a world position.
+    pub fn tile_at(&self, position: Vec3) -> IVec2 {
+        (position.xz() / self.tile_size).round().as_ivec2()
+    }
+
+    /// Snaps a direction on the X and Z axes to one of the grid directions.
+    pub fn snap_direction(&self, dir: Vec2) -> IVec2 {
+        if dir == Vec2::ZERO {
+            return IVec2::ZERO;
+        }
+        let sector = TAU / self.directions.max(1) as f32;
+        let angle = (dir.to_angle() / sector).round() * sector;
+        Vec2::from_angle(angle).round().as_ivec2()
+    }
+}
+
+/// Gap kept between the capsule and what it touches.
+const SKIN: f32 = 0.05;
+
+/// Sideways offsets of the wall rays across the capsule, as fractions of its radius.
+const WALL_RAYS: [f32; 5] = [-1., -0.7, 0., 0.7, 1.];
+
 impl Plugin for PlayerPlugin {
     fn build(&self, app: &mut App) {
+        init_pixel_input(app);
         app
-        .add_systems(Update, player_movement);
+        .register_type::<Player>()
+        .register_type::<StaticCollider>()
+        .register_type::<CharacterController>()
+        .register_type::<GridMovement>()
+        .register_type::<GridPosition>()
+        .register_type::<BlockedTiles>()
+        .init_resource::<BlockedTiles>()
+        .add_systems(Update, (place_on_grid, player_movement, grid_movement).chain());
     }
 }
 
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
 fn player_movement(
-    mut player: Single<(&mut Transform, &mut Player), (With<Player>, Without<PixelCamera>)>,
-    mut cam: Single<(&Camera, &Transform, &mut PixelCamera), With<PixelCamera>>,
-    key_input: ResMut<ButtonInput<KeyCode>>,
+    mut player: Single<(&mut Transform, &mut CharacterController), (With<Player>, Without<PixelCamera>, Without<GridMovement>)>,
+    cam: Single<&Transform, With<PixelCamera>>,
+    input: Res<PixelInput>,
+    actions: Res<ButtonInput<PixelAction>>,
+    map: Res<PixelInputMap>,
+    colliders: Query<(), With<StaticCollider>>,
+    mut ray_cast: MeshRayCast,
     time: Res<Time>,
-    window: Res<WindowSize>,
 ){
-    let right = cam.1.right().mul_add(Vec3::ONE, Vec3::ZERO);
-    let forward = cam.1.forward().mul_add(Vec3::ONE, Vec3::ZERO).with_y(0.);
+    let (transform, controller) = &mut *player;
+    let dt = time.delta_secs();
+    let right = cam.right().mul_add(Vec3::ONE, Vec3::ZERO).with_y(0.).normalize_or_zero();
+    let forward = cam.forward().mul_add(Vec3::ONE, Vec3::ZERO).with_y(0.).normalize_or_zero();
 
-    let mut dir = Vec3::splat(0.0);
-    if key_input.pressed(KeyCode::KeyA) {
-        dir.x -= 1.0;
+    let filter = |entity| colliders.contains(entity);
+    let settings = MeshRayCastSettings::default()
+        .with_filter(&filter)
+        .with_visibility(RayCastVisibility::Any);
+    let mut cast = |origin: Vec3, dir: Vec3, max: f32| {
+        let dir = Dir3::new(dir).ok()?;
+        ray_cast
+            .cast_ray(Ray3d::new(origin, dir), &settings)
+            .first()
+            .map(|(_, hit)| (hit.distance, hit.normal.normalize_or_zero()))
+            .filter(|(distance, _)| *distance <= max)
+    };
+
+    let half_height = controller.height / 2.;
+    let min_ground_y = controller.max_slope.cos();
+
+    if controller.grounded && actions.just_pressed(PixelAction::Jump) {
+        controller.velocity.y = controller.jump_speed;
+        controller.grounded = false;
+    }
+    if !controller.grounded {
+        controller.velocity.y -= controller.gravity * dt;
+    }
+
+    // collide and slide horizontally, with rays from above the step height up to the head
+    // and across the capsule's width, each measured from the capsule's front at its offset
+    let wish = (input.movement.x * right + input.movement.y * forward) * map.move_speed;
+    let mut motion = wish * dt;
+    let mut pos = transform.translation;
+    for _ in 0..3 {
+        let distance = motion.length();
+        if distance < 1e-4 {
+            break;
+        }
+        let dir = motion / distance;
+        let side = dir.cross(Vec3::Y).normalize_or_zero();
+        let feet = pos.y - half_height;
+        let hit = [feet + controller.step_height + SKIN, pos.y, pos.y + half_height - SKIN]
+            .into_iter()
+            .flat_map(|y| WALL_RAYS.map(|offset| (y, offset)))
+            .filter_map(|(y, offset)| {
+                let front = controller.radius * (1. - offset * offset).sqrt();
+                let origin = pos.with_y(y) + side * offset * controller.radius;
+                cast(origin, dir, front + distance).map(|(hit, normal)| (hit - front, normal))
+            })
+            .min_by(|a, b| a.0.total_cmp(&b.0));
+        let Some((hit_distance, normal)) = hit else {
+            pos += motion;
+            break;
+        };
+        let allowed = (hit_distance - SKIN).clamp(0., distance);
+        pos += dir * allowed;
+        // walls and slopes too steep to walk up are slid along horizontally
+        let normal = normal.with_y(0.).normalize_or_zero();
+        let remaining = motion - dir * allowed;
+        motion = remaining - normal * remaining.dot(normal).min(0.);
+    }
+
+    // fall, land, or snap down steps and slopes while walking
+    let dy = controller.velocity.y * dt;
+    if dy > 0. {
+        if let Some((distance, _)) = cast(pos + Vec3::Y * (half_height - SKIN), Vec3::Y, dy + SKIN) {
+            pos.y += (distance - SKIN).max(0.);
+            controller.velocity.y = 0.;
+        } else {
+            pos.y += dy;
+        }
+    } else {
+        let snap = if controller.grounded { controller.step_height } else { 0. };
+        let origin = pos - Vec3::Y * (half_height - controller.step_height);
+        match cast(origin, Vec3::NEG_Y, controller.step_height - dy + snap) {
+            Some((distance, normal)) if normal.y >= min_ground_y => {
+                pos.y += controller.step_height - distance;
+                controller.velocity.y = 0.;
+                controller.grounded = true;
+            }
+            Some((distance, normal)) => {
+                // too steep: stop at the surface and slide the rest of the fall down it
+                pos.y += controller.step_height - distance;
+                let slide = Vec3::Y * (dy + distance - controller.step_height).min(0.);
+                pos += slide - normal * slide.dot(normal);
+                controller.grounded = false;
+            }
+            None => {
+                pos.y += dy;
+                controller.grounded = false;
+            }
+        }
+    }
+    transform.translation = pos;
+
+    // turn to face the way it walks
+    if let Ok(facing) = Dir3::new(wish.with_y(0.)) {
+        let target = Transform::default().looking_to(facing, Vec3::Y).rotation;
+        let t = if controller.turn_speed > 0. { (controller.turn_speed * dt).min(1.) } else { 1. };
+        transform.rotation = transform.rotation.slerp(target, t);
+    }
+}
+
+fn place_on_grid(
+    mut entities: Query<(&GridMovement, &mut GridPosition, &Transform), Added<GridMovement>>,
+){
+    for (grid, mut position, transform) in &mut entities {
+        *position = GridPosition {
+            tile: grid.tile_at(transform.translation),
+            facing: grid.snap_direction((transform.forward().xz()).normalize_or_zero()),
+            ..default()
+        };
+    }
+}
+
+#[allow(clippy::type_complexity)]
+fn grid_movement(
+    mut player: Single<(&mut Transform, &GridMovement, &mut GridPosition, Option<&SteppedAnimation>), (With<Player>, Without<PixelCamera>)>,
+    cam: Single<&Transform, With<PixelCamera>>,
+    input: Res<PixelInput>,
+    blocked: Res<BlockedTiles>,
+    time: Res<Time>,
+){
+    let (transform, grid, position, stepped) = &mut *player;
+    let right = cam.right().xz().normalize_or_zero();
+    let forward = cam.forward().xz().normalize_or_zero();
+    let dir = grid.snap_direction(input.movement.x * right + input.movement.y * forward);
+
+    if let Some(target) = position.target {
+        if dir != IVec2::ZERO {
+            position.buffered = Some(dir);
+        }
+        let delta = stepped.map_or(time.delta_secs(), |stepped| stepped.delta_secs());
+        position.progress += delta / grid.step_duration.max(f32::EPSILON);
+        if position.progress >= 1. {
+            position.tile = target;
+            position.target = None;
+            position.progress = 0.;
+        }
     }
-    if key_input.pressed(KeyCode::KeyD) {
-        dir.x += 1.0;
+
+    if position.target.is_none() {
+        let next = position.buffered.take().or(Some(dir)).filter(|dir| *dir != IVec2::ZERO);
+        if let Some(next) = next {
+            position.facing = next;
+            if blocked.can_move(position.tile, next) {
+                position.target = Some(position.tile + next);
+            }
+        }
     }
-    if key_input.pressed(KeyCode::KeyW) {
-        dir.y += 1.0;
+
+    let from = grid.tile_center(position.tile);
+    let to = position.target.map_or(from, |target| grid.tile_center(target));
+    let xz = from.lerp(to, position.progress);
+    transform.translation.x = xz.x;
+    transform.translation.z = xz.y;
+    if position.facing != IVec2::ZERO {
+        transform.look_to(Vec3::new(position.facing.x as f32, 0., position.facing.y as f32), Vec3::Y);
     }
-    if key_input.pressed(KeyCode::KeyS) {
-        dir.y -= 1.0;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn diagonals_cannot_cut_blocked_corners() {
+        let mut blocked = BlockedTiles::default();
+        blocked.block(IVec2::new(1, 0));
+        assert!(!blocked.can_move(IVec2::ZERO, IVec2::new(1, 0)));
+        assert!(!blocked.can_move(IVec2::ZERO, IVec2::new(1, 1)));
+        assert!(!blocked.can_move(IVec2::ZERO, IVec2::new(1, -1)));
+        assert!(blocked.can_move(IVec2::ZERO, IVec2::new(0, 1)));
+        assert!(blocked.can_move(IVec2::ZERO, IVec2::new(-1, 1)));
+
+        let mut blocked = BlockedTiles::default();
+        blocked.block(IVec2::new(0, 1));
+        assert!(!blocked.can_move(IVec2::ZERO, IVec2::new(1, 1)));
+        assert!(blocked.can_move(IVec2::ZERO, IVec2::new(1, -1)));
+        blocked.unblock(IVec2::new(0, 1));
+        assert!(blocked.can_move(IVec2::ZERO, IVec2::new(1, 1)));
+    }
+
+    #[test]
+    fn four_way_snaps_to_the_closest_axis() {
+        let grid = GridMovement::default();
+        assert_eq!(grid.snap_direction(Vec2::ZERO), IVec2::ZERO);
+        assert_eq!(grid.snap_direction(Vec2::new(1., 0.4)), IVec2::new(1, 0));
+        assert_eq!(grid.snap_direction(Vec2::new(0.4, 1.)), IVec2::new(0, 1));
+        assert_eq!(grid.snap_direction(Vec2::new(-1., -0.2)), IVec2::new(-1, 0));
+        assert_eq!(grid.snap_direction(Vec2::new(0.9, -1.)), IVec2::new(0, -1));
     }
 
-    dir = dir.normalize_or_zero();
-    let mut pos = dir.x * right * time.delta_secs() * 50. + dir.y * forward * time.delta_secs() * 50.;
-    
-    player.0.translation += pos;
+    #[test]
+    fn eight_way_allows_diagonals() {
+        let grid = GridMovement {
+            directions: 8,
+            ..default()
+        };
+        assert_eq!(grid.snap_direction(Vec2::new(1., 0.9)), IVec2::new(1, 1));
+        assert_eq!(grid.snap_direction(Vec2::new(1., 0.3)), IVec2::new(1, 0));
+        assert_eq!(grid.snap_direction(Vec2::new(-0.9, -1.)), IVec2::new(-1, -1));
+        assert_eq!(grid.snap_direction(Vec2::new(-1., 0.)), IVec2::new(-1, 0));
+    }
+
+    #[test]
+    fn tiles_round_to_the_nearest_centre_at_negative_coordinates() {
+        let grid = GridMovement::default();
+        assert_eq!(grid.tile_at(Vec3::new(-7.4, 10., -7.4)), IVec2::ZERO);
+        assert_eq!(grid.tile_at(Vec3::new(-7.6, 0., -22.6)), IVec2::new(-1, -2));
+        assert_eq!(grid.tile_at(Vec3::new(-22.4, 0., 22.4)), IVec2::new(-1, 1));
+        // halfway between centres rounds away from zero
+        assert_eq!(grid.tile_at(Vec3::new(-7.5, 0., 7.5)), IVec2::new(-1, 1));
+        assert_eq!(grid.tile_center(grid.tile_at(Vec3::new(-31., 0., -44.))), Vec2::new(-30., -45.));
+    }
 }
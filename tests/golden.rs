@@ -0,0 +1,288 @@
+//! Golden-image tests for the pixel camera and post-process.
+//!
+//! Each test renders a fixed scene without a window, saves the canvas to `target/tmp/golden/actual`,
+//! and compares it against the reference PNG in `tests/golden`. Differences above the tolerance
+//! fail the test and write a diff image to `target/tmp/golden/diff`, with differing texels in red.
+//!
+//! They always render with the platform's software adapter (lavapipe on Linux, WARP on Windows),
+//! so the references don't depend on the GPU running them. On Linux that means the Mesa Vulkan
+//! drivers must be installed (`mesa-vulkan-drivers` on Debian and Ubuntu). `WGPU_BACKEND` narrows
+//! the backends searched for it. So the default test run doesn't need them, the tests are ignored:
+//!
+//! ```sh
+//! cargo test --test golden -- --ignored
+//! ```
+//!
+//! Set `GOLDEN_BLESS=1` to write the rendered images as the new references after an intended change.
+
+use std::{
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use bevy::{
+    log::LogPlugin,
+    prelude::*,
+    render::{
+        renderer::{initialize_renderer, RenderInstance, WgpuWrapper},
+        settings::{Backends, RenderCreation, WgpuSettings},
+        RenderPlugin,
+    },
+    tasks::block_on,
+    window::ExitCondition,
+    winit::WinitPlugin,
+};
+use bevy_pixelated_3d::*;
+use image::{Rgb, RgbImage};
+
+/// Largest difference allowed in any colour channel of a texel.
+const CHANNEL_TOLERANCE: u8 = 8;
+
+/// Fraction of texels allowed to differ by more than [`CHANNEL_TOLERANCE`].
+const MAX_DIFFERING: f32 = 0.002;
+
+/// Frames to render before capturing, so assets are uploaded and shadows settle.
+const WARMUP_FRAMES: u32 = 10;
+
+/// Frames to wait for the capture before giving up.
+const CAPTURE_FRAMES: u32 = 120;
+
+/// Renderers are not shared between tests, so run them one at a time.
+static RENDER_LOCK: Mutex<()> = Mutex::new(());
+
+#[test]
+#[ignore = "needs the software Vulkan adapter, run with --ignored"]
+fn demo_tray_and_capsule() {
+    check_golden("tray", PixelCamSettings { outer_camera: false, ..default() }, PostProcessSettings::default(), tray_scene);
+}
+
+#[test]
+#[ignore = "needs the software Vulkan adapter, run with --ignored"]
+fn primitives() {
+    check_golden("primitives", ldr(), PostProcessSettings::default(), primitives_scene);
+}
+
+#[test]
+#[ignore = "needs the software Vulkan adapter, run with --ignored"]
+fn primitives_without_outline() {
+    let settings = PostProcessSettings {
+        outline: false,
+        ..default()
+    };
+    check_golden("primitives_no_outline", ldr(), settings, primitives_scene);
+}
+
+#[test]
+#[ignore = "needs the software Vulkan adapter, run with --ignored"]
+fn primitives_dithered_palette() {
+    let settings = PostProcessSettings {
+        dither: true,
+        color_depth: 3,
+        ..default()
+    };
+    check_golden("primitives_dither", ldr(), settings, primitives_scene);
+}
+
+fn ldr() -> PixelCamSettings {
+    PixelCamSettings {
+        hdr: false,
+        outer_camera: false,
+        ..default()
+    }
+}
+
+/// The demo's tray with its walls and the capsule standing in it.
+fn tray_scene(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    let grey = materials.add(Color::linear_rgb(0.5, 0.5, 0.5));
+    commands.spawn((
+        Mesh3d(meshes.add(Cuboid::from_size(Vec3::new(1., 0., 1.)))),
+        MeshMaterial3d(grey.clone()),
+        Transform::from_scale(Vec3::splat(90.)),
+    )).with_children(|parent| {
+        for (size, position) in [
+            (Vec3::new(1.05, 0.15, 0.05), Vec3::new(0., 0.075, 0.5)),
+            (Vec3::new(1.05, 0.15, 0.05), Vec3::new(0., 0.075, -0.5)),
+            (Vec3::new(0.05, 0.15, 1.05), Vec3::new(0.5, 0.075, 0.)),
+            (Vec3::new(0.05, 0.15, 1.05), Vec3::new(-0.5, 0.075, 0.)),
+        ] {
+            parent.spawn((
+                Mesh3d(meshes.add(Cuboid::from_size(size))),
+                MeshMaterial3d(grey.clone()),
+                Transform::from_translation(position),
+            ));
+        }
+    });
+    commands.spawn((
+        Mesh3d(meshes.add(Capsule3d::new(0.5, 1.0))),
+        MeshMaterial3d(materials.add(Color::linear_rgb(0.1, 0.5, 0.1))),
+        Transform::from_xyz(0., 12.5, 0.).with_scale(Vec3::splat(12.)),
+    ));
+    spawn_light(&mut commands);
+}
+
+/// A row of primitives at fixed angles, so each shows several faces and silhouette edges.
+fn primitives_scene(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    commands.spawn((
+        Mesh3d(meshes.add(Cuboid::from_size(Vec3::new(120., 0., 60.)))),
+        MeshMaterial3d(materials.add(Color::linear_rgb(0.4, 0.4, 0.45))),
+    ));
+    let shapes: [(Mesh, Color); 5] = [
+        (Cuboid::from_length(12.).into(), Color::linear_rgb(0.8, 0.2, 0.2)),
+        (Sphere::new(7.).mesh().uv(16, 8), Color::linear_rgb(0.2, 0.8, 0.2)),
+        (Cylinder::new(6., 14.).into(), Color::linear_rgb(0.2, 0.2, 0.8)),
+        (Cone::new(7., 14.).into(), Color::linear_rgb(0.8, 0.8, 0.2)),
+        (Torus::new(3., 7.).into(), Color::linear_rgb(0.8, 0.2, 0.8)),
+    ];
+    for (i, (mesh, color)) in shapes.into_iter().enumerate() {
+        let angle = (i as f32 + 1.) * 0.35;
+        commands.spawn((
+            Mesh3d(meshes.add(mesh)),
+            MeshMaterial3d(materials.add(color)),
+            Transform::from_xyz(i as f32 * 22. - 44., 8., 0.)
+                .with_rotation(Quat::from_euler(EulerRot::YXZ, angle, angle * 0.5, 0.)),
+        ));
+    }
+    spawn_light(&mut commands);
+}
+
+fn spawn_light(commands: &mut Commands) {
+    commands.spawn((
+        DirectionalLight {
+            shadows_enabled: true,
+            illuminance: 4000.,
+            ..default()
+        },
+        Transform::from_rotation(Quat::from_euler(EulerRot::ZYX, 0., 0.75 * std::f32::consts::PI, -0.25 * std::f32::consts::PI)),
+    ));
+}
+
+/// Renders `scene` with the given settings and compares the canvas against `tests/golden/<name>.png`.
+fn check_golden<M>(name: &str, cam_settings: PixelCamSettings, post_process: PostProcessSettings, scene: impl IntoScheduleConfigs<bevy::ecs::system::ScheduleSystem, M>) {
+    let _lock = RENDER_LOCK.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    let out = Path::new(env!("CARGO_TARGET_TMPDIR")).join("golden");
+    let actual_path = out.join("actual").join(format!("{name}.png"));
+    let reference_path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden").join(format!("{name}.png"));
+
+    render(&actual_path, cam_settings, post_process, scene);
+    let actual = image::open(&actual_path).expect("rendered image").to_rgb8();
+
+    if std::env::var_os("GOLDEN_BLESS").is_some() {
+        std::fs::create_dir_all(reference_path.parent().unwrap()).unwrap();
+        actual.save(&reference_path).unwrap();
+        return;
+    }
+    let Ok(reference) = image::open(&reference_path) else {
+        panic!("no reference image at {}, run with GOLDEN_BLESS=1 to create it", reference_path.display());
+    };
+    let reference = reference.to_rgb8();
+    assert_eq!(actual.dimensions(), reference.dimensions(), "{name}: canvas size changed");
+
+    let (diff, differing) = diff_images(&actual, &reference);
+    let total = (actual.width() * actual.height()) as f32;
+    if differing as f32 / total > MAX_DIFFERING {
+        let diff_path = out.join("diff").join(format!("{name}.png"));
+        std::fs::create_dir_all(diff_path.parent().unwrap()).unwrap();
+        diff.save(&diff_path).unwrap();
+        panic!(
+            "{name}: {differing} texels differ from {}, see {}",
+            reference_path.display(),
+            diff_path.display(),
+        );
+    }
+}
+
+/// Runs a windowless app until the canvas has been saved to `path`.
+fn render<M>(path: &Path, cam_settings: PixelCamSettings, post_process: PostProcessSettings, scene: impl IntoScheduleConfigs<bevy::ecs::system::ScheduleSystem, M>) {
+    let mut app = App::new();
+    app
+        .insert_resource(cam_settings)
+        .add_plugins((
+            DefaultPlugins
+                .set(ImagePlugin::default_nearest())
+                .set(WindowPlugin {
+                    primary_window: None,
+                    exit_condition: ExitCondition::DontExit,
+                    ..default()
+                })
+                .set(RenderPlugin {
+                    render_creation: software_renderer(),
+                    synchronous_pipeline_compilation: true,
+                    ..default()
+                })
+                .disable::<WinitPlugin>()
+                .disable::<LogPlugin>(),
+            PixelCamPlugin,
+            PostProcessPlugin,
+            PixelScreenshotPlugin,
+        ))
+        .add_systems(Startup, scene)
+        .add_systems(PostStartup, move |mut settings: Single<&mut PostProcessSettings, With<PixelCamera>>| {
+            **settings = post_process;
+        });
+    app.finish();
+    app.cleanup();
+
+    for _ in 0..WARMUP_FRAMES {
+        app.update();
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    app.world_mut().send_event(PixelScreenshot::new(PathBuf::from(path)));
+    for _ in 0..CAPTURE_FRAMES {
+        app.update();
+        let saved = app.world_mut().resource_mut::<Events<PixelScreenshotSaved>>().drain().next();
+        if let Some(saved) = saved {
+            saved.result.expect("saving the canvas");
+            return;
+        }
+    }
+    panic!("the canvas was not captured within {CAPTURE_FRAMES} frames");
+}
+
+/// Creates the renderer on the software adapter, never a GPU or the GL backend, which can't
+/// read the depth prepass the post-process needs.
+fn software_renderer() -> RenderCreation {
+    let settings = WgpuSettings {
+        backends: Some(Backends::from_env().unwrap_or(Backends::PRIMARY)),
+        ..default()
+    };
+    let instance = wgpu::Instance::new(&wgpu::InstanceDescriptor {
+        backends: settings.backends.unwrap(),
+        flags: settings.instance_flags,
+        ..default()
+    });
+    let options = wgpu::RequestAdapterOptions {
+        force_fallback_adapter: true,
+        ..default()
+    };
+    if block_on(instance.request_adapter(&options)).is_none() {
+        panic!("no software rendering adapter, on Linux install the Mesa Vulkan drivers (lavapipe)");
+    }
+    let (device, queue, adapter_info, adapter) = block_on(initialize_renderer(&instance, &settings, &options));
+    RenderCreation::manual(device, queue, adapter_info, adapter, RenderInstance(Arc::new(WgpuWrapper::new(instance))))
+}
+
+/// Returns an image of `actual` dimmed, with texels that differ from `reference` in red, and how many differ.
+fn diff_images(actual: &RgbImage, reference: &RgbImage) -> (RgbImage, u32) {
+    let mut differing = 0;
+    let diff = RgbImage::from_fn(actual.width(), actual.height(), |x, y| {
+        let a = actual.get_pixel(x, y);
+        let r = reference.get_pixel(x, y);
+        let over = a.0.iter().zip(r.0).any(|(a, r)| a.abs_diff(r) > CHANNEL_TOLERANCE);
+        if over {
+            differing += 1;
+            Rgb([255, 0, 0])
+        } else {
+            Rgb(a.0.map(|c| c / 3))
+        }
+    });
+    (diff, differing)
+}